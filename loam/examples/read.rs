@@ -3,7 +3,7 @@ pub mod node;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let r = loam::Reader::new("test.loam")?;
     let id = r.root()?;
-    let root: node::Node = r.lookup(id)?;
+    let root: node::Node = r.lookup(id.cast())?;
     dbg!(root);
     Ok(())
 }
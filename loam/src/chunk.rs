@@ -0,0 +1,298 @@
+// chunk.rs    Chunk module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::commit::Commit;
+use crate::common::{Error, Id, Result};
+use crate::header::Header;
+use crate::snapshot::Checkpoint;
+use bincode::Options;
+use std::ops::Range;
+
+/// Chunk flag: data is compressed
+pub const COMPRESSED: u8 = 1 << 0;
+
+/// Chunk flag: checkpoint
+pub const CHECKPOINT: u8 = 1 << 1;
+
+/// Chunk flag: checkpoint refers to a commit record
+pub const COMMIT: u8 = 1 << 2;
+
+/// Chunk flag: padding for block alignment
+pub const PADDING: u8 = 1 << 3;
+
+/// All valid chunk flags
+const FLAGS_ALL: u8 = COMPRESSED | CHECKPOINT | COMMIT | PADDING;
+
+/// Location of a chunk within a buffer
+#[derive(Clone, Debug)]
+pub struct Span {
+    /// Chunk Id
+    pub id: Id,
+
+    /// Chunk flags
+    pub flags: u8,
+
+    /// Range of chunk data
+    pub data: Range<usize>,
+
+    /// End of chunk (including checksum)
+    pub end: usize,
+}
+
+impl Span {
+    /// Decode the span of a chunk in a buffer
+    ///
+    /// The chunk must fit within the buffer, and its checksum (if any) must
+    /// be valid.
+    pub fn decode(buf: &[u8], header: &Header, id: Id) -> Result<Self> {
+        Self::decode_at(buf, 0, header, id)
+    }
+
+    /// Decode the span of a chunk in a buffer starting at a file `offset`
+    ///
+    /// The ranges of the span are relative to the buffer.
+    pub fn decode_at(
+        buf: &[u8],
+        offset: usize,
+        header: &Header,
+        id: Id,
+    ) -> Result<Self> {
+        let base = id.to_usize().wrapping_sub(offset);
+        if id.to_usize() < header.len().max(offset) || base >= buf.len() {
+            return Err(Error::InvalidId(id));
+        }
+        let flags_sz = header.flags_size();
+        let flags = buf[base..base + flags_sz].first().copied().unwrap_or(0);
+        if flags & !FLAGS_ALL != 0 {
+            return Err(Error::InvalidId(id));
+        }
+        let options = bincode::DefaultOptions::new().allow_trailing_bytes();
+        let dlen: u64 = options.deserialize(&buf[base + flags_sz..])?;
+        let start = base + flags_sz + options.serialized_size(&dlen)? as usize;
+        let crc_sz = header.checksum().size();
+        let end = usize::try_from(dlen)
+            .ok()
+            .and_then(|dlen| start.checked_add(dlen))
+            .filter(|end| {
+                end.checked_add(crc_sz).is_some_and(|end| end <= buf.len())
+            })
+            .ok_or(Error::InvalidId(id))?;
+        if let Some(checksum) = header.checksum().calculate(&buf[base..end]) {
+            let calced = &checksum.to_le_bytes()[..];
+            let stored = &buf[end..end + crc_sz];
+            if calced != stored {
+                return Err(Error::InvalidCrc(id));
+            }
+        }
+        Ok(Span {
+            id,
+            flags,
+            data: start..end,
+            end: end + crc_sz,
+        })
+    }
+
+    /// Check if chunk data is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.flags & COMPRESSED != 0
+    }
+
+    /// Check if chunk is padding
+    pub fn is_padding(&self) -> bool {
+        self.flags & PADDING != 0
+    }
+
+    /// Get a checkpoint chunk
+    ///
+    /// A checkpoint contains 8 bytes of data, which must be an `Id` of an
+    /// earlier chunk (or zero).  With integrity hashes, this is followed by
+    /// the running digest of all earlier chunks, and then a signature (if
+    /// enabled).  Legacy files have no chunk flags, so any
+    /// chunk matching that description could be a checkpoint.
+    ///
+    /// With the `COMMIT` flag, the `Id` refers to a commit record, which
+    /// contains the root `Id`.
+    pub fn checkpoint(
+        &self,
+        buf: &[u8],
+        header: &Header,
+    ) -> Option<Checkpoint> {
+        let flags = if header.is_legacy() { 0 } else { CHECKPOINT };
+        if self.flags & !COMMIT != flags {
+            return None;
+        }
+        let (bytes, data) = buf[self.data.clone()].split_first_chunk()?;
+        let digest_sz = header.integrity().size();
+        if data.len() != digest_sz + header.signature().size() {
+            return None;
+        }
+        let digest = data[..digest_sz].try_into().ok();
+        let root = Id::from_le_bytes(*bytes);
+        if root.to_usize() >= self.id.to_usize() {
+            return None;
+        }
+        if self.flags & COMMIT == 0 {
+            return Some(Checkpoint::new(self.id, root, digest));
+        }
+        let span = Span::decode(buf, header, root).ok()?;
+        let commit = Commit::decode(&buf[span.data]).ok()?;
+        let before = |id: &Id| id.to_usize() < root.to_usize();
+        (before(&commit.root) && commit.roots.iter().all(|(_, id)| before(id)))
+            .then(|| {
+                Checkpoint::new(self.id, commit.root, digest).with_commit(root)
+            })
+    }
+}
+
+/// Chunk found while scanning a __loam__ file
+#[derive(Clone, Debug)]
+pub struct Chunk<'a> {
+    /// Chunk span
+    span: Span,
+
+    /// Chunk data
+    data: &'a [u8],
+
+    /// Checkpoint (checkpoint chunks only)
+    checkpoint: Option<Checkpoint>,
+}
+
+/// Iterator of chunks in a __loam__ file
+///
+/// Chunks are scanned sequentially, starting after the header.  Padding
+/// chunks are skipped.  Iteration stops after the first invalid chunk.
+pub struct Chunks<'a> {
+    /// Buffer containing chunks
+    buf: &'a [u8],
+
+    /// File header
+    header: Header,
+
+    /// Offset of next chunk
+    base: usize,
+
+    /// Offsets of all previous chunks (legacy files only)
+    starts: Vec<usize>,
+}
+
+impl<'a> Chunk<'a> {
+    /// Get the chunk `Id`
+    pub fn id(&self) -> Id {
+        self.span.id
+    }
+
+    /// Get the length of chunk data
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if chunk data is empty
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Get the raw chunk data, as stored in the file
+    ///
+    /// This is serialized, and may be compressed.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Get the chunk flags
+    #[cfg(feature = "sign")]
+    pub(crate) fn flags(&self) -> u8 {
+        self.span.flags
+    }
+
+    /// Check if chunk data is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.span.is_compressed()
+    }
+
+    /// Check if the chunk is a checkpoint
+    pub fn is_checkpoint(&self) -> bool {
+        self.checkpoint.is_some()
+    }
+
+    /// Get the checkpoint (checkpoint chunks only)
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint
+    }
+
+    /// Get the root `Id` (checkpoint chunks only)
+    pub fn root(&self) -> Option<Id> {
+        self.checkpoint.map(|cp| cp.root())
+    }
+
+    /// Get the end of the chunk (including checksum)
+    pub(crate) fn end(&self) -> usize {
+        self.span.end
+    }
+}
+
+impl<'a> Chunks<'a> {
+    /// Create a new chunk iterator
+    pub(crate) fn new(buf: &'a [u8], header: Header) -> Self {
+        Chunks {
+            buf,
+            header,
+            base: header.len(),
+            starts: Vec::new(),
+        }
+    }
+
+    /// Start scanning at an offset (which must be the start of a chunk)
+    ///
+    /// Legacy checkpoints cannot be detected without scanning from the
+    /// beginning.
+    pub(crate) fn starting_at(mut self, base: usize) -> Self {
+        if !self.header.is_legacy() {
+            self.base = base;
+        }
+        self
+    }
+
+    /// Check if an `Id` is the start of a previous chunk
+    fn is_start(&self, id: Id) -> bool {
+        !self.header.is_legacy()
+            || !id.is_valid()
+            || self.starts.binary_search(&id.to_usize()).is_ok()
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.base >= self.buf.len() {
+                return None;
+            }
+            let id = Id::from_usize(self.base);
+            match Span::decode(self.buf, &self.header, id) {
+                Ok(span) if span.is_padding() => self.base = span.end,
+                Ok(span) => {
+                    let checkpoint = span
+                        .checkpoint(self.buf, &self.header)
+                        .filter(|cp| self.is_start(cp.root()));
+                    let data = &self.buf[span.data.clone()];
+                    if self.header.is_legacy() {
+                        self.starts.push(self.base);
+                    }
+                    self.base = span.end;
+                    return Some(Ok(Chunk {
+                        span,
+                        data,
+                        checkpoint,
+                    }));
+                }
+                Err(e) => {
+                    // stop scanning after an invalid chunk
+                    self.base = self.buf.len();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
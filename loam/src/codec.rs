@@ -0,0 +1,144 @@
+// codec.rs    Serialization codec module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::common::{Error, Result};
+use bincode::Options;
+use serde::Serialize;
+use serde::de::{Deserialize, DeserializeOwned};
+
+/// Serialization codec for chunk data
+///
+/// The codec is recorded in the file header, so a `Reader` always uses the
+/// same codec as the `Writer` which created the file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Codec {
+    /// [bincode] (version 1, with variable-size integers)
+    ///
+    /// [bincode]: https://github.com/bincode-org/bincode
+    Bincode,
+
+    /// [postcard] (requires `postcard` feature)
+    ///
+    /// [postcard]: https://github.com/jamesmunns/postcard
+    Postcard,
+
+    /// [CBOR] (requires `cbor` feature)
+    ///
+    /// [CBOR]: https://cbor.io
+    Cbor,
+}
+
+/// Type tag for chunk data
+///
+/// When type tags are enabled in the [Header](crate::Header), chunks pushed
+/// with [Writer::push_tagged](crate::Writer::push_tagged) start with this
+/// tag, which is checked by [Reader::lookup_tagged](crate::Reader::lookup_tagged).
+///
+/// The tag is stored in files, so it must be stable.  It should be changed
+/// whenever the serialized form of the type changes.  Zero is used for chunks
+/// pushed without a tag.
+pub trait TypeTag {
+    /// Tag value, unique among types stored in a file
+    const TAG: u32;
+}
+
+impl Codec {
+    /// Get codec from header byte
+    pub(crate) fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Codec::Bincode),
+            1 => Some(Codec::Postcard),
+            2 => Some(Codec::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Check if codec is supported by enabled features
+    pub(crate) fn is_supported(self) -> bool {
+        match self {
+            Codec::Bincode => true,
+            Codec::Postcard => cfg!(feature = "postcard"),
+            Codec::Cbor => cfg!(feature = "cbor"),
+        }
+    }
+
+    /// Check if data can be deserialized with borrowed fields
+    pub(crate) fn can_borrow(self) -> bool {
+        self != Codec::Cbor
+    }
+
+    /// Serialize data
+    pub(crate) fn serialize<D>(self, data: &D) -> Result<Vec<u8>>
+    where
+        D: Serialize,
+    {
+        match self {
+            Codec::Bincode => Ok(bincode::DefaultOptions::new()
+                .with_little_endian()
+                .with_varint_encoding()
+                .serialize(data)?),
+            #[cfg(feature = "postcard")]
+            Codec::Postcard => postcard::to_allocvec(data)
+                .map_err(|e| Error::Codec(e.to_string())),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(data, &mut buf)
+                    .map_err(|e| Error::Codec(e.to_string()))?;
+                Ok(buf)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::UnsupportedHeader),
+        }
+    }
+
+    /// Deserialize owned data
+    ///
+    /// The bincode codec enforces an optional allocation limit.
+    pub(crate) fn deserialize<D>(
+        self,
+        buf: &[u8],
+        limit: Option<usize>,
+    ) -> Result<D>
+    where
+        D: DeserializeOwned,
+    {
+        match self {
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => ciborium::from_reader(buf)
+                .map_err(|e| Error::Codec(e.to_string())),
+            _ => self.deserialize_borrowed(buf, limit),
+        }
+    }
+
+    /// Deserialize data with borrowed fields
+    ///
+    /// The bincode codec enforces an optional allocation limit.
+    pub(crate) fn deserialize_borrowed<'a, D>(
+        self,
+        buf: &'a [u8],
+        limit: Option<usize>,
+    ) -> Result<D>
+    where
+        D: Deserialize<'a>,
+    {
+        match self {
+            Codec::Bincode => {
+                let options =
+                    bincode::DefaultOptions::new().allow_trailing_bytes();
+                match limit {
+                    Some(limit) => {
+                        Ok(options.with_limit(limit as u64).deserialize(buf)?)
+                    }
+                    None => Ok(options.deserialize(buf)?),
+                }
+            }
+            #[cfg(feature = "postcard")]
+            Codec::Postcard => postcard::from_bytes(buf)
+                .map_err(|e| Error::Codec(e.to_string())),
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::UnsupportedHeader),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// commit.rs    Commit record module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::codec::Codec;
+use crate::common::{Id, Result};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Commit record, referenced by a checkpoint with metadata
+///
+/// This is always serialized with bincode, regardless of the file codec.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Commit {
+    /// Sequence number
+    pub seq: u64,
+
+    /// Milliseconds since the UNIX epoch
+    pub timestamp: u64,
+
+    /// Root chunk `Id`
+    pub root: Id,
+
+    /// Named root chunk `Id`s
+    pub roots: Vec<(String, Id)>,
+
+    /// User payload (serialized with file codec)
+    pub payload: Vec<u8>,
+}
+
+/// Checkpoint metadata
+///
+/// Written by [Writer::checkpoint_with](crate::Writer::checkpoint_with) or
+/// [Writer::checkpoint_named](crate::Writer::checkpoint_named).
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// Sequence number
+    seq: u64,
+
+    /// Wall-clock time
+    timestamp: SystemTime,
+
+    /// Named root `Id`s
+    roots: Vec<(String, Id)>,
+
+    /// Serialized payload
+    payload: Vec<u8>,
+
+    /// File codec
+    codec: Codec,
+}
+
+impl Commit {
+    /// Create a new commit record with the current time
+    pub fn new(
+        seq: u64,
+        root: Id,
+        roots: Vec<(String, Id)>,
+        payload: Vec<u8>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Commit {
+            seq,
+            timestamp,
+            root,
+            roots,
+            payload,
+        }
+    }
+
+    /// Encode the commit record
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(bincode::DefaultOptions::new().serialize(self)?)
+    }
+
+    /// Decode a commit record
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        Ok(bincode::DefaultOptions::new().deserialize(buf)?)
+    }
+
+    /// Convert to metadata
+    pub fn into_metadata(self, codec: Codec) -> Metadata {
+        Metadata {
+            seq: self.seq,
+            timestamp: UNIX_EPOCH + Duration::from_millis(self.timestamp),
+            roots: self.roots,
+            payload: self.payload,
+            codec,
+        }
+    }
+}
+
+impl Metadata {
+    /// Get the sequence number
+    ///
+    /// Each checkpoint with metadata increments this by one.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Get the wall-clock time when the checkpoint was written
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Get all named root `Id`s
+    pub fn roots(&self) -> impl Iterator<Item = (&str, Id)> {
+        self.roots.iter().map(|(name, id)| (name.as_str(), *id))
+    }
+
+    /// Get a named root `Id`
+    pub fn root_named(&self, name: &str) -> Option<Id> {
+        self.roots().find(|(n, _id)| *n == name).map(|(_n, id)| id)
+    }
+
+    /// Deserialize the user payload
+    pub fn payload<P>(&self) -> Result<P>
+    where
+        P: DeserializeOwned,
+    {
+        self.codec.deserialize(&self.payload, None)
+    }
+}
@@ -2,8 +2,10 @@
 //
 // Copyright (c) 2021  Douglas P Lau
 //
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 
 /// Errors for reading or writing loam files
 #[derive(Debug, thiserror::Error)]
@@ -16,10 +18,18 @@ pub enum Error {
     #[error("Bincode {0}")]
     Bincode(#[from] Box<bincode::ErrorKind>),
 
+    /// Codec error (other than bincode)
+    #[error("Codec {0}")]
+    Codec(String),
+
     /// Invalid Header
     #[error("Invalid Header")]
     InvalidHeader,
 
+    /// Unsupported header version or options
+    #[error("Unsupported Header")]
+    UnsupportedHeader,
+
     /// Invalid CRC
     #[error("Invalid CRC")]
     InvalidCrc(Id),
@@ -31,66 +41,189 @@ pub enum Error {
     /// Invalid ID
     #[error("Invalid ID")]
     InvalidId(Id),
+
+    /// Invalid compressed data
+    #[error("Invalid Compression")]
+    InvalidCompression(Id),
+
+    /// Encrypted data could not be authenticated (wrong key or tampering)
+    #[error("Invalid Encryption")]
+    InvalidEncryption(Id),
+
+    /// Invalid borrow of data which is not stored as serialized
+    #[error("Invalid Borrow")]
+    InvalidBorrow(Id),
+
+    /// Chunk type tag does not match lookup type
+    #[error("Type Mismatch")]
+    TypeMismatch(Id),
+
+    /// Checkpoint digest does not match chunks
+    #[error("Invalid Digest")]
+    InvalidDigest(Id),
+
+    /// Checkpoint is unsigned, or its signature is not valid
+    #[error("Invalid Signature")]
+    InvalidSignature(Id),
+
+    /// Signing or encryption key required by header is missing
+    #[error("Missing Key")]
+    MissingKey,
+
+    /// Chunk exceeds a size limit
+    #[error("Limit Exceeded")]
+    LimitExceeded(Id),
+
+    /// File is locked by another reader or writer
+    #[error("File Locked")]
+    Locked,
 }
 
 /// Result for reading or writing loam files
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// File header
-pub const HEADER: &[u8; 8] = b"loam0000";
+/// Marker type for an untyped chunk [Id]
+#[derive(Clone, Copy, Debug)]
+pub enum Untyped {}
 
 /// Chunk Identifier
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
-pub struct Id(u64);
+///
+/// An `Id` is typed by the data in its chunk, so looking up the wrong type
+/// is a compile error.  Ids of chunks with different types (or from
+/// checkpoints) are [Untyped], and can be converted with [Id::cast].
+///
+/// It is serialized as a `u64`, regardless of type.
+pub struct Id<T = Untyped> {
+    /// File offset of chunk
+    offset: u64,
+
+    /// Type of chunk data
+    _type: PhantomData<fn() -> T>,
+}
 
-impl fmt::Display for Id {
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Id").field(&self.offset).finish()
+    }
+}
+
+impl<T> fmt::Display for Id<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Id: {:?}", self.0)
+        write!(f, "Id: {:?}", self.offset)
+    }
+}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.offset.hash(state);
+    }
+}
+
+impl<T> Serialize for Id<T> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.offset.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Id<T> {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u64::deserialize(deserializer).map(Id::from_u64)
     }
 }
 
 impl Id {
-    /// Create a new Id
+    /// Create a new untyped Id
     pub fn new(id: u64) -> Self {
-        Id(id)
+        Id::from_u64(id)
+    }
+
+    pub(crate) fn from_le_bytes(bytes: [u8; 8]) -> Self {
+        Id::from_u64(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn from_usize(id: usize) -> Self {
+        Id::from_u64(id as u64)
+    }
+}
+
+impl<T> Id<T> {
+    /// Create an Id from a file offset
+    fn from_u64(offset: u64) -> Self {
+        Id {
+            offset,
+            _type: PhantomData,
+        }
     }
 
     /// Check if Id is valid
     pub fn is_valid(self) -> bool {
-        self.0 > 0
+        self.offset > 0
     }
 
-    pub(crate) fn from_le_bytes(bytes: [u8; 8]) -> Self {
-        Id(u64::from_le_bytes(bytes))
+    /// Cast to an Id of another type
+    ///
+    /// The chunk data is not checked, so the type must be correct.
+    pub fn cast<U>(self) -> Id<U> {
+        Id::from_u64(self.offset)
     }
 
-    pub(crate) fn to_le_bytes(self) -> [u8; 8] {
-        self.0.to_le_bytes()
+    /// Erase the type of an Id
+    pub fn erase(self) -> Id {
+        self.cast()
     }
 
-    pub(crate) fn from_usize(id: usize) -> Self {
-        Id(id as u64)
+    pub(crate) fn to_le_bytes(self) -> [u8; 8] {
+        self.offset.to_le_bytes()
     }
 
     pub(crate) fn to_usize(self) -> usize {
-        self.0 as usize
+        self.offset as usize
     }
 }
 
-#[cfg(feature = "crc")]
-pub const CRC_SZ: usize = 4;
-
-#[cfg(feature = "crc")]
-pub fn checksum(buf: &[u8]) -> Option<u32> {
-    let mut hasher = crc32fast::Hasher::new();
-    hasher.update(&buf);
-    Some(hasher.finalize())
+/// Get a path for a test file, removing it if it exists
+#[cfg(test)]
+pub(crate) fn test_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_file(&path);
+    path
 }
 
-#[cfg(not(feature = "crc"))]
-pub const CRC_SZ: usize = 0;
-
-#[cfg(not(feature = "crc"))]
-pub fn checksum(_buf: &[u8]) -> Option<u32> {
-    None
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typed_id() -> Result<()> {
+        let id: Id<String> = Id::new(42).cast();
+        assert_eq!(id.erase(), Id::new(42));
+        let options = bincode::DefaultOptions::new();
+        let buf = bincode::Options::serialize(options, &id)?;
+        assert_eq!(buf, bincode::Options::serialize(options, &42u64)?);
+        let id: Id<Vec<u8>> = bincode::Options::deserialize(options, &buf)?;
+        assert_eq!(id, Id::new(42).cast());
+        Ok(())
+    }
 }
@@ -0,0 +1,322 @@
+// compact.rs    Compactor module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::codec::TypeTag;
+use crate::common::{Id, Result};
+use crate::reader::Reader;
+use crate::storage::lock_writer;
+use crate::writer::Writer;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+/// Compactor for __loam__ files
+///
+/// Since data is never modified once written, superseded chunks accumulate
+/// in a file.  A compactor copies only the chunks reachable from the root of
+/// the last checkpoint into a new file, which then replaces the original.
+/// The new file has the same header options as the original.
+///
+/// Chunk contents are opaque to loam, so the caller must traverse the tree,
+/// copying each chunk and remapping the `Id`s of its children.
+///
+/// ```no_run
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Deserialize, Serialize)]
+/// struct Node {
+///     children: Vec<loam::Id>,
+/// }
+///
+/// fn copy_node(cx: &mut loam::Compactor, id: loam::Id) -> loam::Result<loam::Id> {
+///     let id = cx.copy_with(id.cast(), |cx, mut node: Node| {
+///         for child in node.children.iter_mut() {
+///             *child = copy_node(cx, *child)?;
+///         }
+///         Ok(node)
+///     })?;
+///     Ok(id.erase())
+/// }
+///
+/// loam::Compactor::compact("tree.loam", copy_node)?;
+/// # Ok::<(), loam::Error>(())
+/// ```
+pub struct Compactor<'a> {
+    /// Reader for original file
+    reader: &'a Reader,
+
+    /// Writer for compacted file
+    writer: Writer,
+
+    /// Mapping of original `Id`s to compacted `Id`s
+    ids: HashMap<Id, Id>,
+}
+
+/// Make path for compacted file
+fn compact_path(path: &Path) -> PathBuf {
+    let mut tmp = OsString::from(path);
+    tmp.push(".compact");
+    PathBuf::from(tmp)
+}
+
+impl Compactor<'_> {
+    /// Compact a file.
+    ///
+    /// The `copy_root` callback is called with the root `Id` of the last
+    /// checkpoint, and must return the root `Id` in the compacted file.  If
+    /// the checkpoint has named roots, it is called once for each of them
+    /// instead.  After a new checkpoint is written, the compacted file
+    /// atomically replaces the original.  Other checkpoint metadata is not
+    /// preserved.
+    ///
+    /// The writer lock is held while compacting, so [Error::Locked] is
+    /// returned if a writer has the file open.
+    ///
+    /// Files with signed checkpoints or encryption cannot be compacted, since
+    /// no keys are available.
+    ///
+    /// [Error::Locked]: crate::Error::Locked
+    pub fn compact<P, F>(path: P, mut copy_root: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&mut Compactor, Id) -> Result<Id>,
+    {
+        let path = path.as_ref();
+        // avoid creating a lock file for a missing path
+        std::fs::metadata(path)?;
+        let _lock = lock_writer(path)?;
+        let tmp = compact_path(path);
+        if tmp.exists() {
+            std::fs::remove_file(&tmp)?;
+        }
+        let reader = Reader::new(path)?;
+        let checkpoint = reader.checkpoint()?;
+        let roots: Vec<(String, Id)> = match reader.metadata(checkpoint)? {
+            Some(meta) => meta
+                .roots()
+                .map(|(name, id)| (name.to_string(), id))
+                .collect(),
+            None => Vec::new(),
+        };
+        let header = reader.header().current();
+        let file = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .append(true)
+            .open(&tmp)?;
+        let mut compactor = Compactor {
+            reader: &reader,
+            writer: Writer::with_storage(file, header)?,
+            ids: HashMap::new(),
+        };
+        let res = if roots.is_empty() {
+            copy_root(&mut compactor, checkpoint.root())
+                .and_then(|root| compactor.writer.checkpoint(root))
+        } else {
+            roots
+                .iter()
+                .map(|(name, id)| {
+                    Ok((name.as_str(), copy_root(&mut compactor, *id)?))
+                })
+                .collect::<Result<Vec<_>>>()
+                .and_then(|roots| compactor.writer.checkpoint_named(roots))
+        };
+        drop(compactor);
+        drop(reader);
+        match res {
+            Ok(()) => Ok(std::fs::rename(&tmp, path)?),
+            Err(e) => {
+                std::fs::remove_file(&tmp)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Copy a chunk which contains no child `Id`s.
+    ///
+    /// The serialized data is copied without being deserialized.
+    ///
+    /// # Returns
+    /// `Id` of chunk in compacted file
+    pub fn copy<T>(&mut self, id: Id<T>) -> Result<Id<T>> {
+        let id = id.erase();
+        if let Some(nid) = self.ids.get(&id) {
+            return Ok(nid.cast());
+        }
+        let nid = self.writer.push_raw(&self.reader.lookup_raw(id)?)?;
+        self.ids.insert(id, nid);
+        Ok(nid.cast())
+    }
+
+    /// Copy a chunk, remapping its child `Id`s.
+    ///
+    /// The `remap` callback receives the chunk data, and must replace all
+    /// child `Id`s with the values returned by copying each child.  Chunks
+    /// which are reachable more than once are only copied once.
+    ///
+    /// # Returns
+    /// `Id` of chunk in compacted file
+    pub fn copy_with<D, F>(&mut self, id: Id<D>, remap: F) -> Result<Id<D>>
+    where
+        D: Serialize + DeserializeOwned,
+        F: FnOnce(&mut Self, D) -> Result<D>,
+    {
+        self.copy_with_tag(id, 0, remap)
+    }
+
+    /// Copy a chunk with a type tag, remapping its child `Id`s.
+    ///
+    /// This is like [Compactor::copy_with], for chunks pushed with
+    /// [Writer::push_tagged].  The tag is checked and kept.
+    ///
+    /// # Returns
+    /// `Id` of chunk in compacted file
+    pub fn copy_tagged<D, F>(&mut self, id: Id<D>, remap: F) -> Result<Id<D>>
+    where
+        D: Serialize + DeserializeOwned + TypeTag,
+        F: FnOnce(&mut Self, D) -> Result<D>,
+    {
+        self.copy_with_tag(id, D::TAG, remap)
+    }
+
+    /// Copy a chunk with a type tag (if enabled), remapping its child `Id`s
+    fn copy_with_tag<D, F>(
+        &mut self,
+        id: Id<D>,
+        tag: u32,
+        remap: F,
+    ) -> Result<Id<D>>
+    where
+        D: Serialize + DeserializeOwned,
+        F: FnOnce(&mut Self, D) -> Result<D>,
+    {
+        if let Some(nid) = self.ids.get(&id.erase()) {
+            return Ok(nid.cast());
+        }
+        let data = self.reader.lookup_with_tag(id, tag)?;
+        let data = remap(self, data)?;
+        let nid = self.writer.push_with_tag(&data, tag)?;
+        self.ids.insert(id.erase(), nid.erase());
+        Ok(nid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test_path;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Node {
+        children: Vec<Id>,
+    }
+
+    fn copy_node(cx: &mut Compactor, id: Id) -> Result<Id> {
+        let id = cx.copy_with(id.cast(), |cx, mut node: Node| {
+            for child in node.children.iter_mut() {
+                *child = cx.copy(*child)?;
+            }
+            Ok(node)
+        })?;
+        Ok(id.erase())
+    }
+
+    #[test]
+    fn compact() -> Result<()> {
+        let path = test_path("loam-compact.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"a")?;
+        let root = writer.push(&Node {
+            children: vec![a.erase()],
+        })?;
+        writer.checkpoint(root)?;
+        let b = writer.push(&"b")?;
+        let root = writer.push(&Node {
+            children: vec![a.erase(), b.erase(), b.erase()],
+        })?;
+        writer.checkpoint(root)?;
+        drop(writer);
+        let len = std::fs::metadata(&path)?.len();
+        Compactor::compact(&path, copy_node)?;
+        assert!(std::fs::metadata(&path)?.len() < len);
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.checkpoints()?.len(), 1);
+        let node: Node = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(node.children.len(), 3);
+        assert_eq!(node.children[1], node.children[2]);
+        let msg: String = reader.lookup(node.children[0].cast())?;
+        assert_eq!(msg, "a");
+        let msg: String = reader.lookup(node.children[1].cast())?;
+        assert_eq!(msg, "b");
+        Ok(())
+    }
+
+    #[test]
+    fn compact_named() -> Result<()> {
+        let path = test_path("loam-compact-named.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"a")?.erase();
+        let tree = writer.push(&Node { children: vec![a] })?.erase();
+        writer.push(&"garbage")?;
+        let table = writer.push(&"table")?.erase();
+        writer.checkpoint_named([("tree", tree), ("table", table)])?;
+        drop(writer);
+        let len = std::fs::metadata(&path)?.len();
+        Compactor::compact(&path, |cx, id| {
+            if id == tree {
+                copy_node(cx, id)
+            } else {
+                cx.copy(id)
+            }
+        })?;
+        assert!(std::fs::metadata(&path)?.len() < len);
+        let reader = Reader::new(&path)?;
+        let tree = reader.root_named("tree")?.unwrap();
+        let node: Node = reader.lookup(tree.cast())?;
+        let msg: String = reader.lookup(node.children[0].cast())?;
+        assert_eq!(msg, "a");
+        let table = reader.root_named("table")?.unwrap();
+        let msg: String = reader.lookup(table.cast())?;
+        assert_eq!(msg, "table");
+        assert_eq!(reader.root_named("missing")?, None);
+        Ok(())
+    }
+
+    impl TypeTag for Node {
+        const TAG: u32 = 0x4E4F_4445;
+    }
+
+    #[test]
+    fn compact_tagged() -> Result<()> {
+        use crate::Header;
+        let path = test_path("loam-compact-tagged.loam");
+        let header = Header::default().with_type_tags(true);
+        let mut writer = Writer::with_header(&path, header)?;
+        let a = writer.push(&"a")?.erase();
+        writer.push(&"garbage")?;
+        let root = writer.push_tagged(&Node { children: vec![a] })?;
+        writer.checkpoint(root)?;
+        drop(writer);
+        let len = std::fs::metadata(&path)?.len();
+        Compactor::compact(&path, |cx, id| {
+            let id = cx.copy_tagged(id.cast(), |cx, mut node: Node| {
+                for child in node.children.iter_mut() {
+                    *child = cx.copy(*child)?;
+                }
+                Ok(node)
+            })?;
+            Ok(id.erase())
+        })?;
+        assert!(std::fs::metadata(&path)?.len() < len);
+        let reader = Reader::new(&path)?;
+        assert!(reader.header().type_tags());
+        let node: Node = reader.lookup_tagged(reader.root()?.cast())?;
+        let msg: String = reader.lookup(node.children[0].cast())?;
+        assert_eq!(msg, "a");
+        Ok(())
+    }
+}
@@ -0,0 +1,765 @@
+// header.rs    File header module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::chunk::{Chunks, Span};
+use crate::codec::Codec;
+use crate::common::{Error, Id, Result};
+
+/// Magic bytes at start of file
+const MAGIC: &[u8; 4] = b"loam";
+
+/// Legacy (version 0.0) header
+const LEGACY: &[u8; 8] = b"loam0000";
+
+/// Current major / minor version
+const VERSION: &[u8; 4] = b"0100";
+
+/// Length of current header
+const HEADER_LEN: usize = 16;
+
+/// Offset of options in current header
+const OPTIONS: usize = 8;
+
+/// Minimum block alignment (log2)
+const ALIGN_MIN: u8 = 6;
+
+/// Maximum block alignment (log2)
+const ALIGN_MAX: u8 = 24;
+
+/// Checksum algorithm
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    /// No checksums
+    None,
+
+    /// CRC-32 checksums
+    Crc32,
+}
+
+/// Compression algorithm
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// No compression
+    None,
+
+    /// LZ4 compression (requires `compress` feature)
+    Lz4,
+}
+
+/// Integrity hash algorithm
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Integrity {
+    /// No integrity hashes
+    None,
+
+    /// BLAKE3 hash chain (requires `integrity` feature)
+    Blake3,
+}
+
+/// Checkpoint signature algorithm
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Signature {
+    /// No signatures
+    None,
+
+    /// Ed25519 signatures (requires `sign` feature)
+    Ed25519,
+}
+
+/// Chunk encryption algorithm
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encryption {
+    /// No encryption
+    None,
+
+    /// XChaCha20-Poly1305 AEAD (requires `encrypt` feature)
+    XChaCha20Poly1305,
+}
+
+/// File header
+///
+/// The header records the format options used for all chunks in a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Header {
+    /// Legacy version (0.0) file
+    legacy: bool,
+
+    /// Checksum algorithm
+    checksum: Checksum,
+
+    /// Compression algorithm
+    compression: Compression,
+
+    /// Serialization codec
+    codec: Codec,
+
+    /// Type tags on data chunks
+    type_tags: bool,
+
+    /// Integrity hash algorithm
+    integrity: Integrity,
+
+    /// Checkpoint signature algorithm
+    signature: Signature,
+
+    /// Chunk encryption algorithm
+    encryption: Encryption,
+
+    /// Block alignment of chunks (log2), or zero
+    align_bits: u8,
+}
+
+impl Checksum {
+    /// Get checksum from header byte
+    fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Checksum::None),
+            1 => Some(Checksum::Crc32),
+            _ => None,
+        }
+    }
+
+    /// Get size of checksum in bytes
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32 => 4,
+        }
+    }
+
+    /// Calculate checksum of a buffer
+    pub(crate) fn calculate(self, buf: &[u8]) -> Option<u32> {
+        match self {
+            Checksum::None => None,
+            Checksum::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(buf);
+                Some(hasher.finalize())
+            }
+        }
+    }
+}
+
+impl Compression {
+    /// Get compression from header byte
+    fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Check if compression is supported by enabled features
+    fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Lz4 => cfg!(feature = "compress"),
+        }
+    }
+
+    /// Compress a buffer
+    #[cfg_attr(not(feature = "compress"), allow(unused_variables))]
+    pub(crate) fn compress(self, buf: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "compress")]
+            Compression::Lz4 => Some(lz4_flex::compress_prepend_size(buf)),
+            #[cfg(not(feature = "compress"))]
+            Compression::Lz4 => None,
+        }
+    }
+
+    /// Get the decompressed size of a compressed buffer
+    pub(crate) fn decompressed_size(self, buf: &[u8]) -> Option<usize> {
+        match self {
+            Compression::None => None,
+            Compression::Lz4 => {
+                let size = buf.first_chunk()?;
+                usize::try_from(u32::from_le_bytes(*size)).ok()
+            }
+        }
+    }
+
+    /// Decompress a buffer
+    #[cfg_attr(not(feature = "compress"), allow(unused_variables))]
+    pub(crate) fn decompress(self, buf: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "compress")]
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(buf).ok(),
+            #[cfg(not(feature = "compress"))]
+            Compression::Lz4 => None,
+        }
+    }
+}
+
+impl Integrity {
+    /// Get integrity from header byte
+    fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Integrity::None),
+            1 => Some(Integrity::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Check if integrity is supported by enabled features
+    fn is_supported(self) -> bool {
+        match self {
+            Integrity::None => true,
+            Integrity::Blake3 => cfg!(feature = "integrity"),
+        }
+    }
+
+    /// Get size of digest in bytes
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Integrity::None => 0,
+            Integrity::Blake3 => 32,
+        }
+    }
+
+    /// Fold a buffer into a running digest
+    #[cfg_attr(not(feature = "integrity"), allow(unused_variables))]
+    pub(crate) fn fold(self, digest: &mut [u8; 32], buf: &[u8]) {
+        match self {
+            Integrity::None => (),
+            #[cfg(feature = "integrity")]
+            Integrity::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(digest);
+                hasher.update(buf);
+                *digest = *hasher.finalize().as_bytes();
+            }
+            #[cfg(not(feature = "integrity"))]
+            Integrity::Blake3 => (),
+        }
+    }
+}
+
+impl Signature {
+    /// Get signature from header byte
+    fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Signature::None),
+            1 => Some(Signature::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// Check if signature is supported by enabled features
+    fn is_supported(self) -> bool {
+        match self {
+            Signature::None => true,
+            Signature::Ed25519 => cfg!(feature = "sign"),
+        }
+    }
+
+    /// Get size of signature in bytes
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Signature::None => 0,
+            Signature::Ed25519 => 64,
+        }
+    }
+}
+
+impl Encryption {
+    /// Get encryption from header byte
+    fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Encryption::None),
+            1 => Some(Encryption::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// Check if encryption is supported by enabled features
+    fn is_supported(self) -> bool {
+        match self {
+            Encryption::None => true,
+            Encryption::XChaCha20Poly1305 => cfg!(feature = "encrypt"),
+        }
+    }
+
+    /// Get the number of bytes added by encryption
+    pub(crate) fn overhead(self) -> usize {
+        match self {
+            Encryption::None => 0,
+            Encryption::XChaCha20Poly1305 => 32,
+        }
+    }
+
+    /// Encrypt a buffer for a chunk `Id`
+    ///
+    /// The nonce is the `Id` followed by random bytes, which are prepended
+    /// to the ciphertext.  The `Id` is also authenticated.
+    #[cfg_attr(not(feature = "encrypt"), allow(unused_variables))]
+    pub(crate) fn encrypt(
+        self,
+        key: &[u8; 32],
+        id: Id,
+        buf: &[u8],
+    ) -> Option<Vec<u8>> {
+        match self {
+            Encryption::None => None,
+            #[cfg(feature = "encrypt")]
+            Encryption::XChaCha20Poly1305 => {
+                use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+                use chacha20poly1305::{XChaCha20Poly1305, aead::Payload};
+                let cipher = XChaCha20Poly1305::new(key.into());
+                let aad = id.to_le_bytes();
+                let mut nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                nonce[..aad.len()].copy_from_slice(&aad);
+                let msg = Payload {
+                    msg: buf,
+                    aad: &aad,
+                };
+                let sealed = cipher.encrypt(&nonce, msg).ok()?;
+                let mut data = nonce[aad.len()..].to_vec();
+                data.extend(sealed);
+                Some(data)
+            }
+            #[cfg(not(feature = "encrypt"))]
+            Encryption::XChaCha20Poly1305 => None,
+        }
+    }
+
+    /// Decrypt and authenticate a buffer for a chunk `Id`
+    #[cfg_attr(not(feature = "encrypt"), allow(unused_variables))]
+    pub(crate) fn decrypt(
+        self,
+        key: &[u8; 32],
+        id: Id,
+        buf: &[u8],
+    ) -> Option<Vec<u8>> {
+        match self {
+            Encryption::None => None,
+            #[cfg(feature = "encrypt")]
+            Encryption::XChaCha20Poly1305 => {
+                use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+                use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+                let cipher = XChaCha20Poly1305::new(key.into());
+                let aad = id.to_le_bytes();
+                let mut nonce = XNonce::default();
+                let (salt, sealed) =
+                    buf.split_at_checked(nonce.len() - aad.len())?;
+                nonce[..aad.len()].copy_from_slice(&aad);
+                nonce[aad.len()..].copy_from_slice(salt);
+                let msg = Payload {
+                    msg: sealed,
+                    aad: &aad,
+                };
+                cipher.decrypt(&nonce, msg).ok()
+            }
+            #[cfg(not(feature = "encrypt"))]
+            Encryption::XChaCha20Poly1305 => None,
+        }
+    }
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        let checksum = if cfg!(feature = "crc") {
+            Checksum::Crc32
+        } else {
+            Checksum::None
+        };
+        let compression = if cfg!(feature = "compress") {
+            Compression::Lz4
+        } else {
+            Compression::None
+        };
+        Header {
+            legacy: false,
+            checksum,
+            compression,
+            codec: Codec::Bincode,
+            type_tags: false,
+            integrity: Integrity::None,
+            signature: Signature::None,
+            encryption: Encryption::None,
+            align_bits: 0,
+        }
+    }
+}
+
+impl Header {
+    /// Set the checksum algorithm
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Set the compression algorithm
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the serialization codec
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Enable or disable type tags
+    ///
+    /// When enabled, each data chunk starts with a 4-byte [TypeTag], which
+    /// is zero for chunks pushed without one.  Looking up a chunk with a
+    /// different tag returns [Error::TypeMismatch].
+    ///
+    /// [TypeTag]: crate::TypeTag
+    pub fn with_type_tags(mut self, type_tags: bool) -> Self {
+        self.type_tags = type_tags;
+        self
+    }
+
+    /// Set the integrity hash algorithm
+    ///
+    /// When enabled, every chunk is folded into a running digest, which is
+    /// stored in each checkpoint.  The digests can be checked with
+    /// [Reader::verify](crate::Reader::verify).
+    pub fn with_integrity(mut self, integrity: Integrity) -> Self {
+        self.integrity = integrity;
+        self
+    }
+
+    /// Set the checkpoint signature algorithm
+    ///
+    /// When enabled, each checkpoint is signed by the writer's signing key,
+    /// which can be checked with
+    /// [Reader::verify_signed](crate::Reader::verify_signed).  Integrity
+    /// hashes are also required, so that signatures cover all chunks.
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Set the chunk encryption algorithm
+    ///
+    /// When enabled, the data of every chunk (except checkpoints and their
+    /// metadata) is encrypted and authenticated.  Both writers and readers
+    /// need the key, which is set with
+    /// [Writer::set_encryption_key](crate::Writer::set_encryption_key) or
+    /// [Reader::set_encryption_key](crate::Reader::set_encryption_key).
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Set the block alignment of chunks
+    ///
+    /// When set, padding is added so that chunks do not straddle a block
+    /// boundary.  Chunks larger than one block start on a boundary instead.
+    /// The block size is rounded up to a power of two, from 64 bytes to 16
+    /// MiB.  `None` disables alignment.
+    pub fn with_alignment(mut self, block: Option<usize>) -> Self {
+        self.align_bits = block.map_or(0, |block| {
+            let block = block.clamp(1 << ALIGN_MIN, 1 << ALIGN_MAX);
+            block.next_power_of_two().trailing_zeros() as u8
+        });
+        self
+    }
+
+    /// Get the checksum algorithm
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Get the compression algorithm
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Get the serialization codec
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Check if data chunks have type tags
+    pub fn type_tags(&self) -> bool {
+        self.type_tags
+    }
+
+    /// Get the integrity hash algorithm
+    pub fn integrity(&self) -> Integrity {
+        self.integrity
+    }
+
+    /// Get the checkpoint signature algorithm
+    pub fn signature(&self) -> Signature {
+        self.signature
+    }
+
+    /// Get the chunk encryption algorithm
+    pub fn encryption(&self) -> Encryption {
+        self.encryption
+    }
+
+    /// Get the block alignment of chunks
+    pub fn alignment(&self) -> Option<usize> {
+        (self.align_bits > 0).then(|| 1 << self.align_bits)
+    }
+
+    /// Check if the file has a legacy (version 0.0) header
+    ///
+    /// Legacy files have no chunk flags, and no record of checksum options.
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    /// Get the length of the header in bytes
+    pub(crate) fn len(&self) -> usize {
+        if self.legacy {
+            LEGACY.len()
+        } else {
+            HEADER_LEN
+        }
+    }
+
+    /// Get the size of chunk flags in bytes
+    pub(crate) fn flags_size(&self) -> usize {
+        if self.legacy { 0 } else { 1 }
+    }
+
+    /// Get the size of a checkpoint chunk in bytes
+    pub(crate) fn checkpoint_size(&self) -> usize {
+        self.flags_size()
+            + 9
+            + self.integrity.size()
+            + self.signature.size()
+            + self.checksum.size()
+    }
+
+    /// Get a current version header with the same options
+    pub(crate) fn current(&self) -> Self {
+        Header {
+            legacy: false,
+            ..*self
+        }
+    }
+
+    /// Check if all options are supported by enabled features
+    pub(crate) fn check_supported(&self) -> Result<()> {
+        let legacy = self.compression == Compression::None
+            && self.codec == Codec::Bincode
+            && !self.type_tags
+            && self.integrity == Integrity::None
+            && self.signature == Signature::None
+            && self.encryption == Encryption::None
+            && self.align_bits == 0;
+        let signed = self.signature == Signature::None
+            || self.integrity != Integrity::None;
+        if (legacy || !self.legacy)
+            && signed
+            && self.compression.is_supported()
+            && self.codec.is_supported()
+            && self.integrity.is_supported()
+            && self.signature.is_supported()
+            && self.encryption.is_supported()
+        {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedHeader)
+        }
+    }
+
+    /// Encode the header
+    pub(crate) fn encode(&self) -> Result<Vec<u8>> {
+        if self.legacy {
+            return Err(Error::UnsupportedHeader);
+        }
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(VERSION);
+        buf.push(self.checksum as u8);
+        buf.push(self.compression as u8);
+        buf.push(self.codec as u8);
+        buf.push(self.type_tags.into());
+        buf.push(self.integrity as u8);
+        buf.push(self.signature as u8);
+        buf.push(self.encryption as u8);
+        buf.push(self.align_bits);
+        Ok(buf)
+    }
+
+    /// Decode the header of a file
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.starts_with(LEGACY) {
+            return Ok(Header::decode_legacy(buf));
+        }
+        if !buf.starts_with(MAGIC) || buf.len() < HEADER_LEN {
+            return Err(Error::InvalidHeader);
+        }
+        if &buf[MAGIC.len()..OPTIONS] != VERSION {
+            return Err(Error::UnsupportedHeader);
+        }
+        let checksum =
+            Checksum::from_u8(buf[OPTIONS]).ok_or(Error::UnsupportedHeader)?;
+        let compression = Compression::from_u8(buf[OPTIONS + 1])
+            .ok_or(Error::UnsupportedHeader)?;
+        let codec =
+            Codec::from_u8(buf[OPTIONS + 2]).ok_or(Error::UnsupportedHeader)?;
+        let type_tags = match buf[OPTIONS + 3] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::UnsupportedHeader),
+        };
+        let integrity = Integrity::from_u8(buf[OPTIONS + 4])
+            .ok_or(Error::UnsupportedHeader)?;
+        let signature = Signature::from_u8(buf[OPTIONS + 5])
+            .ok_or(Error::UnsupportedHeader)?;
+        let encryption = Encryption::from_u8(buf[OPTIONS + 6])
+            .ok_or(Error::UnsupportedHeader)?;
+        let align_bits = buf[OPTIONS + 7];
+        if align_bits != 0 && !(ALIGN_MIN..=ALIGN_MAX).contains(&align_bits) {
+            return Err(Error::UnsupportedHeader);
+        }
+        let header = Header {
+            legacy: false,
+            checksum,
+            compression,
+            codec,
+            type_tags,
+            integrity,
+            signature,
+            encryption,
+            align_bits,
+        };
+        header.check_supported()?;
+        Ok(header)
+    }
+
+    /// Decode a legacy header
+    ///
+    /// Checksums were enabled by a cargo feature, so check whether the file
+    /// ends with a checkpoint with a valid CRC.  If not (a torn file), scan
+    /// chunks with CRCs for any valid checkpoint.  Chunks without CRCs are
+    /// very unlikely to pass CRC checks by chance.
+    fn decode_legacy(buf: &[u8]) -> Self {
+        let header = Header {
+            legacy: true,
+            checksum: Checksum::None,
+            compression: Compression::None,
+            codec: Codec::Bincode,
+            type_tags: false,
+            integrity: Integrity::None,
+            signature: Signature::None,
+            encryption: Encryption::None,
+            align_bits: 0,
+        };
+        let crc = header.with_checksum(Checksum::Crc32);
+        if let Some(base) = buf.len().checked_sub(crc.checkpoint_size())
+            && let Ok(span) = Span::decode(buf, &crc, Id::from_usize(base))
+            && span.end == buf.len()
+            && span.data.len() == 8
+        {
+            return crc;
+        }
+        let mut chunks = Chunks::new(buf, crc);
+        if chunks.any(|chunk| chunk.is_ok_and(|c| c.is_checkpoint())) {
+            crc
+        } else {
+            header
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_decode() -> Result<()> {
+        let header = Header::default().with_checksum(Checksum::Crc32);
+        let buf = header.encode()?;
+        assert_eq!(&buf[..8], b"loam0100");
+        assert_eq!(Header::decode(&buf)?, header);
+        let header = header.with_checksum(Checksum::None);
+        assert_eq!(Header::decode(&header.encode()?)?, header);
+        let tagged = header.with_type_tags(true);
+        assert_eq!(Header::decode(&tagged.encode()?)?, tagged);
+        let mut buf = header.encode()?;
+        buf[10] = 2;
+        assert_eq!(
+            Header::decode(&buf).ok(),
+            cfg!(feature = "cbor").then_some(header.with_codec(Codec::Cbor))
+        );
+        let mut buf = header.encode()?;
+        buf[12] = 1;
+        assert_eq!(
+            Header::decode(&buf).ok(),
+            cfg!(feature = "integrity")
+                .then_some(header.with_integrity(Integrity::Blake3))
+        );
+        // signatures require integrity hashes
+        buf[13] = 1;
+        assert_eq!(
+            Header::decode(&buf).ok(),
+            cfg!(feature = "sign").then_some(
+                header
+                    .with_integrity(Integrity::Blake3)
+                    .with_signature(Signature::Ed25519)
+            )
+        );
+        buf[12] = 0;
+        assert!(Header::decode(&buf).is_err());
+        let mut buf = header.encode()?;
+        buf[15] = 12;
+        assert_eq!(Header::decode(&buf)?, header.with_alignment(Some(4000)));
+        assert_eq!(Header::decode(&buf)?.alignment(), Some(4096));
+        buf[15] = 3;
+        assert!(Header::decode(&buf).is_err());
+        let mut buf = header.encode()?;
+        buf[14] = 1;
+        assert_eq!(
+            Header::decode(&buf).ok(),
+            cfg!(feature = "encrypt").then_some(
+                header.with_encryption(Encryption::XChaCha20Poly1305)
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            Header::decode(b"loaf0100\0\0\0\0\0\0\0\0"),
+            Err(Error::InvalidHeader)
+        ));
+        assert!(matches!(
+            Header::decode(b"loam0200\0\0\0\0\0\0\0\0"),
+            Err(Error::UnsupportedHeader)
+        ));
+        assert!(matches!(
+            Header::decode(b"loam0100\x07\0\0\0\0\0\0\0"),
+            Err(Error::UnsupportedHeader)
+        ));
+    }
+
+    #[test]
+    fn legacy() -> Result<()> {
+        // legacy file with a string chunk and checkpoint
+        let mut buf = b"loam0000\x03\x02hi\x08".to_vec();
+        buf.extend(8u64.to_le_bytes());
+        let header = Header::decode(&buf)?;
+        assert!(header.is_legacy());
+        assert_eq!(header.checksum(), Checksum::None);
+        // same file with CRCs
+        let mut buf = b"loam0000\x03\x02hi".to_vec();
+        let crc = Checksum::Crc32.calculate(b"\x03\x02hi").unwrap();
+        buf.extend(crc.to_le_bytes());
+        let mut chunk = vec![8];
+        chunk.extend(8u64.to_le_bytes());
+        let crc = Checksum::Crc32.calculate(&chunk).unwrap();
+        buf.extend(chunk);
+        buf.extend(crc.to_le_bytes());
+        let header = Header::decode(&buf)?;
+        assert!(header.is_legacy());
+        assert_eq!(header.checksum(), Checksum::Crc32);
+        Ok(())
+    }
+}
@@ -5,10 +5,29 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
+mod chunk;
+mod codec;
+mod commit;
 mod common;
+mod compact;
+mod header;
 mod reader;
+mod snapshot;
+mod storage;
 mod writer;
 
-pub use common::{Error, Id, Result};
-pub use reader::Reader;
-pub use writer::Writer;
+pub use chunk::{Chunk, Chunks};
+pub use codec::{Codec, TypeTag};
+pub use commit::Metadata;
+pub use common::{Error, Id, Result, Untyped};
+pub use compact::Compactor;
+pub use header::{
+    Checksum, Compression, Encryption, Header, Integrity, Signature,
+};
+pub use reader::{Reader, Recovery};
+pub use snapshot::{Checkpoint, Snapshot};
+pub use storage::Storage;
+pub use writer::{Durability, Writer};
+
+#[cfg(feature = "sign")]
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
@@ -2,85 +2,809 @@
 //
 // Copyright (c) 2021-2025  Douglas P Lau
 //
-use crate::common::{CRC_SZ, Error, HEADER, Id, Result};
-use bincode::Options;
-use memmap2::{Mmap, MmapMut};
+use crate::chunk::{Chunk, Chunks, Span};
+use crate::codec::TypeTag;
+use crate::commit::{Commit, Metadata};
+use crate::common::{Error, Id, Result};
+use crate::header::{Encryption, Header, Integrity};
+use crate::snapshot::{Checkpoint, Snapshot};
+use crate::storage::{Bytes, lock_shared};
+use memmap2::Mmap;
+use serde::Deserialize;
 use serde::de::DeserializeOwned;
+use std::borrow::Cow;
 use std::fs::File;
 use std::path::Path;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
 /// Reader for __loam__ files
+///
+/// Files are memory-mapped, but a reader can also be created from an
+/// in-memory buffer.
 pub struct Reader {
-    /// Memory map of loam file
-    mmap: Mmap,
+    /// Mapped file (for refreshing)
+    file: Option<File>,
 
-    /// Length of memory map
+    /// Bytes of loam file
+    bytes: Bytes,
+
+    /// File header
+    header: Header,
+
+    /// Length of bytes (through last checkpoint after refresh)
     len: usize,
+
+    /// Maximum chunk data length
+    max_chunk_len: Option<usize>,
+
+    /// Maximum allocation per lookup
+    max_alloc: Option<usize>,
+
+    /// Sorted index of chunk starts (if built)
+    index: Option<Vec<usize>>,
+
+    /// Key for decrypting chunks
+    encryption_key: Option<[u8; 32]>,
+}
+
+/// Map a file into memory
+fn map_file(file: &File) -> Result<Mmap> {
+    // This is safe as long as the file is only appended, never modified.
+    // Writers and readers hold advisory locks to enforce this, but other
+    // processes could ignore them.  Needless to say, don't do that!
+    Ok(unsafe { Mmap::map(file)? })
+}
+
+/// Result of scanning a file for the last valid checkpoint
+#[derive(Clone, Copy, Debug)]
+pub struct Recovery {
+    /// Root `Id` of last valid checkpoint
+    root: Option<Id>,
+
+    /// Length of file through last valid checkpoint
+    valid_len: u64,
+
+    /// Total length of file
+    file_len: u64,
 }
 
-/// Size of checkpoint chunk in bytes
-const CHECKPOINT_SZ: usize = 9 + CRC_SZ;
+impl Recovery {
+    /// Get the root `Id` of the last valid checkpoint
+    pub fn root(&self) -> Option<Id> {
+        self.root
+    }
+
+    /// Get the length of the file through the last valid checkpoint
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// Get the number of bytes after the last valid checkpoint
+    pub fn torn_len(&self) -> u64 {
+        self.file_len - self.valid_len
+    }
+
+    /// Check if the file has torn data after the last valid checkpoint
+    pub fn is_torn(&self) -> bool {
+        self.torn_len() > 0
+    }
+}
 
 impl Reader {
     /// Create a new empty Reader
     pub fn new_empty() -> Result<Self> {
-        let len = 1;
-        let mmap = MmapMut::map_anon(len)?.make_read_only()?;
-        Ok(Self { mmap, len })
+        let bytes = Bytes::Static(&[]);
+        let header = Header::default();
+        Ok(Self {
+            file: None,
+            bytes,
+            header,
+            len: 0,
+            max_chunk_len: None,
+            max_alloc: None,
+            index: None,
+            encryption_key: None,
+        })
     }
 
     /// Create a new Reader
+    ///
+    /// A shared lock is held on the file while the reader exists, which
+    /// prevents [Writer::recover](crate::Writer::recover) from truncating it.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file = File::open(path)?;
-        // This is safe as long as the file is not modified by another process.
-        // Needless to say, don't do that!
-        let mmap = unsafe { Mmap::map(&file)? };
-        let len = mmap.len();
-        if len >= HEADER.len() && HEADER == &mmap[..HEADER.len()] {
-            Ok(Reader { mmap, len })
+        lock_shared(&file)?;
+        Self::from_file(file)
+    }
+
+    /// Create a Reader for an open file
+    pub(crate) fn from_file(file: File) -> Result<Self> {
+        let mut reader = Self::with_bytes(Bytes::Mmap(map_file(&file)?))?;
+        reader.file = Some(file);
+        Ok(reader)
+    }
+
+    /// Create a Reader from an in-memory buffer
+    ///
+    /// The buffer can be a `Vec<u8>`, such as one created by a `Writer`.
+    pub fn from_bytes<B>(buf: B) -> Result<Self>
+    where
+        B: Into<Arc<[u8]>>,
+    {
+        Self::with_bytes(Bytes::Shared(buf.into()))
+    }
+
+    /// Create a Reader from a static buffer, without copying
+    ///
+    /// This can be used with files embedded by `include_bytes!`.
+    pub fn from_static(buf: &'static [u8]) -> Result<Self> {
+        Self::with_bytes(Bytes::Static(buf))
+    }
+
+    /// Create a Reader from bytes
+    fn with_bytes(bytes: Bytes) -> Result<Self> {
+        let header = Header::decode(&bytes)?;
+        let len = bytes.len();
+        Ok(Reader {
+            file: None,
+            bytes,
+            header,
+            len,
+            max_chunk_len: None,
+            max_alloc: None,
+            index: None,
+            encryption_key: None,
+        })
+    }
+
+    /// Refresh the reader to the newest checkpoint.
+    ///
+    /// If the file has grown since it was mapped, it is remapped and any
+    /// appended chunks are scanned.  Chunks after the newest checkpoint are
+    /// ignored, since they may still be being written.
+    ///
+    /// # Returns
+    /// `true` if the reader moved to a newer checkpoint
+    pub fn refresh(&mut self) -> Result<bool> {
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+        if file.metadata()?.len() > self.bytes.len() as u64 {
+            self.bytes = Bytes::Mmap(map_file(file)?);
+        }
+        // start scanning at the last checkpoint, if possible
+        let base = if !self.header.is_legacy() && self.checkpoint().is_ok() {
+            self.len
         } else {
-            Err(Error::InvalidHeader)
+            self.header.len()
+        };
+        let mut len = None;
+        let mut starts = Vec::new();
+        let chunks = Chunks::new(&self.bytes, self.header).starting_at(base);
+        for chunk in chunks {
+            let Ok(chunk) = chunk else { break };
+            starts.push(chunk.id().to_usize());
+            if chunk.is_checkpoint() {
+                len = Some(chunk.end());
+            }
+        }
+        match len {
+            Some(len) if len != self.len => {
+                if let Some(index) = &mut self.index {
+                    let last = index.last().copied().unwrap_or(0);
+                    let starts = starts.into_iter();
+                    index.extend(starts.filter(|s| *s > last && *s < len));
+                }
+                self.len = len;
+                Ok(true)
+            }
+            _ => Ok(false),
         }
     }
 
+    /// Poll the file until it has a newer checkpoint.
+    ///
+    /// The reader is refreshed once per `interval`, until a newer checkpoint
+    /// is found or `timeout` has elapsed.
+    ///
+    /// # Returns
+    /// `true` if the reader moved to a newer checkpoint
+    pub fn poll(
+        &mut self,
+        interval: Duration,
+        timeout: Duration,
+    ) -> Result<bool> {
+        let start = Instant::now();
+        loop {
+            if self.refresh()? {
+                return Ok(true);
+            }
+            if start.elapsed() + interval > timeout {
+                return Ok(false);
+            }
+            sleep(interval);
+        }
+    }
+
+    /// Get the file header
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Set the maximum chunk data length.
+    ///
+    /// Looking up a longer chunk returns [Error::LimitExceeded].  `None`
+    /// (the default) allows any chunk which fits within the file.
+    pub fn set_max_chunk_len(&mut self, max_len: Option<usize>) {
+        self.max_chunk_len = max_len;
+    }
+
+    /// Set the maximum allocation for each lookup.
+    ///
+    /// This limits the decompressed length of chunk data, and the memory
+    /// allocated by the bincode codec while deserializing.  With other
+    /// codecs, it only limits the serialized length.  Lookups exceeding the
+    /// limit return an error.  `None` (the default) is unlimited.
+    pub fn set_max_alloc(&mut self, max_alloc: Option<usize>) {
+        self.max_alloc = max_alloc;
+    }
+
+    /// Set the key for decrypting chunks.
+    ///
+    /// This is required to lookup chunks if encryption is enabled in the
+    /// header, or else [Error::MissingKey] is returned.
+    pub fn set_encryption_key(&mut self, key: [u8; 32]) {
+        self.encryption_key = Some(key);
+    }
+
+    /// Get the length of the file in bytes
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Build an index of all chunks.
+    ///
+    /// All chunks in the file are scanned.  After this, looking up an `Id`
+    /// which is not the start of a chunk returns [Error::InvalidId], rather
+    /// than decoding nonsense.  The index is updated by [Reader::refresh].
+    pub fn build_index(&mut self) -> Result<()> {
+        let mut index = Vec::new();
+        for chunk in self.chunks() {
+            index.push(chunk?.id().to_usize());
+        }
+        self.index = Some(index);
+        Ok(())
+    }
+
+    /// Check if an `Id` is the start of a chunk (if indexed)
+    fn check_index(&self, id: Id) -> Result<()> {
+        match &self.index {
+            Some(index) if index.binary_search(&id.to_usize()).is_err() => {
+                Err(Error::InvalidId(id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decode the span of a chunk
+    fn span(&self, id: Id) -> Result<Span> {
+        self.check_index(id)?;
+        let span = Span::decode(&self.bytes[..self.len], &self.header, id)?;
+        if self.max_chunk_len.is_some_and(|max| span.data.len() > max) {
+            return Err(Error::LimitExceeded(id));
+        }
+        Ok(span)
+    }
+
     /// Get the root chunk `Id` from the last checkpoint.
     pub fn root(&self) -> Result<Id> {
-        if self.len >= HEADER.len() + CHECKPOINT_SZ {
-            let base = self.len - CHECKPOINT_SZ;
-            let id = Id::from_usize(base);
-            let bytes: [u8; 8] = self.lookup(id)?;
-            return Ok(Id::from_le_bytes(bytes));
+        Ok(self.checkpoint()?.root())
+    }
+
+    /// Get a named root chunk `Id` from the last checkpoint.
+    ///
+    /// # Returns
+    /// `None` if the checkpoint has no root with that name
+    pub fn root_named(&self, name: &str) -> Result<Option<Id>> {
+        let meta = self.metadata(self.checkpoint()?)?;
+        Ok(meta.and_then(|meta| meta.root_named(name)))
+    }
+
+    /// Get the last checkpoint.
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        let sz = self.header.checkpoint_size();
+        if self.len >= self.header.len() + sz {
+            let id = Id::from_usize(self.len - sz);
+            if let Ok(span) = self.span(id)
+                && span.end == self.len
+                && let Some(checkpoint) =
+                    span.checkpoint(&self.bytes[..self.len], &self.header)
+            {
+                return Ok(checkpoint);
+            }
         }
         Err(Error::InvalidCheckpoint)
     }
 
+    /// Get all checkpoints, from newest to oldest.
+    ///
+    /// All chunks in the file are scanned.
+    pub fn checkpoints(&self) -> Result<Vec<Checkpoint>> {
+        let mut checkpoints = Vec::new();
+        for chunk in self.chunks() {
+            let chunk = chunk?;
+            if let Some(checkpoint) = chunk.checkpoint() {
+                checkpoints.push(checkpoint);
+            }
+        }
+        checkpoints.reverse();
+        Ok(checkpoints)
+    }
+
+    /// Create a point-in-time snapshot rooted at a checkpoint.
+    pub fn snapshot(&self, checkpoint: Checkpoint) -> Result<Snapshot<'_>> {
+        let span = self.span(checkpoint.id())?;
+        if span.checkpoint(&self.bytes[..self.len], &self.header)
+            == Some(checkpoint)
+        {
+            Ok(Snapshot::new(self, checkpoint))
+        } else {
+            Err(Error::InvalidCheckpoint)
+        }
+    }
+
+    /// Get the metadata of a checkpoint.
+    ///
+    /// # Returns
+    /// `None` if the checkpoint has no metadata
+    pub fn metadata(&self, checkpoint: Checkpoint) -> Result<Option<Metadata>> {
+        let Some(id) = checkpoint.commit() else {
+            return Ok(None);
+        };
+        let span = self.span(id)?;
+        let commit = Commit::decode(&self.bytes[span.data])?;
+        Ok(Some(commit.into_metadata(self.header.codec())))
+    }
+
+    /// Get an iterator of all chunks in the file.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks::new(&self.bytes[..self.len], self.header)
+    }
+
+    /// Scan all chunks to find the last valid checkpoint.
+    ///
+    /// Chunks are read sequentially, starting after the header.  Scanning
+    /// stops at the first chunk which is incomplete or has an invalid
+    /// checksum.
+    pub fn recovery(&self) -> Recovery {
+        let mut root = None;
+        let mut valid_len = self.header.len().min(self.len);
+        for chunk in self.chunks() {
+            match chunk {
+                Ok(chunk) => {
+                    if chunk.is_checkpoint() {
+                        root = chunk.root();
+                        valid_len = chunk.end();
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        Recovery {
+            root,
+            valid_len: valid_len as u64,
+            file_len: self.len as u64,
+        }
+    }
+
+    /// Verify the integrity of all chunks.
+    ///
+    /// All chunks are scanned, recalculating the running digest and checking
+    /// it against the digest stored in each checkpoint.  The file must end
+    /// with a checkpoint.  If the header has no integrity hashes,
+    /// [Error::UnsupportedHeader] is returned.
+    pub fn verify(&self) -> Result<()> {
+        self.verify_chain(|_chunk| Ok(()))
+    }
+
+    /// Verify the integrity and signatures of all chunks.
+    ///
+    /// This is like [Reader::verify], but every checkpoint must also be
+    /// signed by the trusted `key`.  Unsigned or forged checkpoints
+    /// (including files without integrity hashes) return
+    /// [Error::InvalidSignature].
+    #[cfg(feature = "sign")]
+    pub fn verify_signed(
+        &self,
+        key: &ed25519_dalek::VerifyingKey,
+    ) -> Result<()> {
+        use crate::header::Signature;
+        if self.header.integrity() == Integrity::None
+            || self.header.signature() == Signature::None
+        {
+            return Err(Error::InvalidSignature(self.checkpoint()?.id()));
+        }
+        let sig_sz = self.header.signature().size();
+        self.verify_chain(|chunk| {
+            let data = chunk.data();
+            let (data, sig) = data.split_at(data.len() - sig_sz);
+            let msg = [&[chunk.flags()][..], data].concat();
+            ed25519_dalek::Signature::from_slice(sig)
+                .and_then(|sig| key.verify_strict(&msg, &sig))
+                .map_err(|_| Error::InvalidSignature(chunk.id()))
+        })
+    }
+
+    /// Verify the running digest, with a check for each checkpoint chunk
+    fn verify_chain<F>(&self, mut check: F) -> Result<()>
+    where
+        F: FnMut(&Chunk) -> Result<()>,
+    {
+        let integrity = self.header.integrity();
+        if integrity == Integrity::None {
+            return Err(Error::UnsupportedHeader);
+        }
+        let mut digest = [0; 32];
+        integrity.fold(&mut digest, &self.bytes[..self.header.len()]);
+        let mut end = None;
+        for chunk in self.chunks() {
+            let chunk = chunk?;
+            if let Some(checkpoint) = chunk.checkpoint() {
+                if checkpoint.digest() != Some(digest) {
+                    return Err(Error::InvalidDigest(chunk.id()));
+                }
+                check(&chunk)?;
+                end = Some(chunk.end());
+            }
+            let buf = &self.bytes[chunk.id().to_usize()..chunk.end()];
+            integrity.fold(&mut digest, buf);
+        }
+        match end {
+            Some(end) if end == self.len => Ok(()),
+            _ => Err(Error::InvalidCheckpoint),
+        }
+    }
+
+    /// Get the running digest of all chunks (for appending)
+    ///
+    /// The digest in the last checkpoint is not verified.
+    pub(crate) fn digest(&self) -> Result<[u8; 32]> {
+        let mut digest = [0; 32];
+        let mut base = 0;
+        if self.len > self.header.len() {
+            let checkpoint = self.checkpoint()?;
+            digest = checkpoint.digest().unwrap_or_default();
+            base = checkpoint.id().to_usize();
+        }
+        let integrity = self.header.integrity();
+        integrity.fold(&mut digest, &self.bytes[base..self.len]);
+        Ok(digest)
+    }
+
     /// Lookup data for the given chunk `Id`
-    pub fn lookup<D>(&self, id: Id) -> Result<D>
+    pub fn lookup<D>(&self, id: Id<D>) -> Result<D>
     where
         D: DeserializeOwned,
     {
-        let base = id.to_usize();
-        if self.len >= HEADER.len() + CHECKPOINT_SZ
-            && base >= HEADER.len()
-            && base < self.len
-        {
-            let options = bincode::DefaultOptions::new().allow_trailing_bytes();
-            let dlen: u64 = options.deserialize(&self.mmap[base..])?;
-            #[cfg(feature = "crc")]
-            {
-                let crcoff = base + dlen as usize + 1;
-                let chunk = &self.mmap[base..crcoff];
-                if let Some(checksum) = crate::common::checksum(chunk) {
-                    let calced = &checksum.to_le_bytes()[..];
-                    let stored = &self.mmap[crcoff..crcoff + CRC_SZ];
-                    if calced != stored {
-                        return Err(Error::InvalidCrc(id));
-                    }
-                }
+        self.lookup_with_tag(id, 0)
+    }
+
+    /// Lookup data with a type tag for the given chunk `Id`
+    ///
+    /// If type tags are enabled in the header, the chunk tag must match, or
+    /// [Error::TypeMismatch] is returned.
+    pub fn lookup_tagged<D>(&self, id: Id<D>) -> Result<D>
+    where
+        D: DeserializeOwned + TypeTag,
+    {
+        self.lookup_with_tag(id, D::TAG)
+    }
+
+    /// Lookup data for the given chunk `Id`, checking its type tag
+    pub(crate) fn lookup_with_tag<D>(&self, id: Id<D>, tag: u32) -> Result<D>
+    where
+        D: DeserializeOwned,
+    {
+        let data = self.lookup_raw(id.erase())?;
+        let data = self.check_data(id, &data, tag)?;
+        self.header.codec().deserialize(data, self.max_alloc)
+    }
+
+    /// Lookup borrowed data for the given chunk `Id`
+    ///
+    /// Data is deserialized directly from the file bytes, so types with
+    /// borrowed fields (such as `&str` or `&[u8]`) can avoid allocation.
+    /// Compressed chunks cannot be borrowed, nor can any chunks using the
+    /// CBOR codec.
+    pub fn lookup_borrowed<'a, D>(&'a self, id: Id<D>) -> Result<D>
+    where
+        D: Deserialize<'a>,
+    {
+        let codec = self.header.codec();
+        match self.lookup_raw(id.erase())? {
+            Cow::Borrowed(data) if codec.can_borrow() => {
+                let data = self.check_data(id, data, 0)?;
+                codec.deserialize_borrowed(data, self.max_alloc)
             }
-            let offset = options.serialized_size(&dlen)? as usize;
-            return Ok(options.deserialize(&self.mmap[base + offset..])?);
+            _ => Err(Error::InvalidBorrow(id.erase())),
+        }
+    }
+
+    /// Check the length and type tag (if enabled) of chunk data
+    ///
+    /// # Returns
+    /// Chunk data, with type tag removed
+    fn check_data<'b, D>(
+        &self,
+        id: Id<D>,
+        data: &'b [u8],
+        tag: u32,
+    ) -> Result<&'b [u8]> {
+        if self.max_alloc.is_some_and(|max| data.len() > max) {
+            return Err(Error::LimitExceeded(id.erase()));
+        }
+        check_tag(&self.header, id.erase(), data, tag)
+    }
+
+    /// Lookup raw (serialized) data for the given chunk `Id`
+    ///
+    /// Encrypted data is decrypted, and compressed data is decompressed.
+    pub(crate) fn lookup_raw(&self, id: Id) -> Result<Cow<'_, [u8]>> {
+        let span = self.span(id)?;
+        decode_raw(
+            &self.header,
+            self.encryption_key.as_ref(),
+            self.max_alloc,
+            &self.bytes,
+            &span,
+        )
+    }
+}
+
+/// Check the type tag (if enabled) of chunk data
+///
+/// # Returns
+/// Chunk data, with type tag removed
+pub(crate) fn check_tag<'b>(
+    header: &Header,
+    id: Id,
+    data: &'b [u8],
+    tag: u32,
+) -> Result<&'b [u8]> {
+    if !header.type_tags() {
+        return Ok(data);
+    }
+    match data.split_first_chunk() {
+        Some((t, data)) if u32::from_le_bytes(*t) == tag => Ok(data),
+        _ => Err(Error::TypeMismatch(id)),
+    }
+}
+
+/// Decode raw (serialized) data from a chunk span
+///
+/// Encrypted data is decrypted, and compressed data is decompressed.
+pub(crate) fn decode_raw<'b>(
+    header: &Header,
+    key: Option<&[u8; 32]>,
+    max_alloc: Option<usize>,
+    buf: &'b [u8],
+    span: &Span,
+) -> Result<Cow<'b, [u8]>> {
+    let id = span.id;
+    if span.is_padding() {
+        return Err(Error::InvalidId(id));
+    }
+    let mut data = Cow::Borrowed(&buf[span.data.clone()]);
+    let encryption = header.encryption();
+    if encryption != Encryption::None {
+        let key = key.ok_or(Error::MissingKey)?;
+        let plain = encryption
+            .decrypt(key, id, &data)
+            .ok_or(Error::InvalidEncryption(id))?;
+        data = Cow::Owned(plain);
+    }
+    if span.is_compressed() {
+        let compression = header.compression();
+        let size = compression
+            .decompressed_size(&data)
+            .ok_or(Error::InvalidCompression(id))?;
+        if max_alloc.is_some_and(|max| size > max) {
+            return Err(Error::LimitExceeded(id));
         }
-        Err(Error::InvalidId(id))
+        return compression
+            .decompress(&data)
+            .map(Cow::Owned)
+            .ok_or(Error::InvalidCompression(id));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Writer;
+    use crate::common::test_path;
+    use crate::header::Checksum;
+    use serde::Serialize;
+
+    #[test]
+    fn checkpoints() -> Result<()> {
+        let path = test_path("loam-checkpoints.loam");
+        let mut writer = Writer::new(&path)?;
+        for msg in ["first", "second", "third"] {
+            let id = writer.push(&msg)?;
+            writer.checkpoint(id)?;
+        }
+        let reader = Reader::new(&path)?;
+        let checkpoints = reader.checkpoints()?;
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(checkpoints[0], reader.checkpoint()?);
+        let snapshot = reader.snapshot(checkpoints[2])?;
+        let msg: String = snapshot.lookup(snapshot.root().cast())?;
+        assert_eq!(msg, "first");
+        let root = checkpoints[1].root();
+        assert!(matches!(
+            snapshot.lookup::<String>(root.cast()),
+            Err(Error::InvalidId(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn snapshot_compressed() -> Result<()> {
+        use crate::header::Compression;
+        let header = Header::default().with_compression(Compression::Lz4);
+        let mut writer = Writer::with_storage(Vec::new(), header)?;
+        let long = "compressible ".repeat(100);
+        let a = writer.push(&long)?;
+        writer.checkpoint(a)?;
+        let reader = Reader::from_bytes(writer.into_storage())?;
+        assert!(reader.chunks().next().unwrap()?.is_compressed());
+        let snapshot = reader.snapshot(reader.checkpoint()?)?;
+        assert_eq!(snapshot.lookup(a)?, long);
+        assert!(matches!(
+            snapshot.lookup_borrowed::<&str>(a.cast()),
+            Err(Error::InvalidBorrow(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn chunks() -> Result<()> {
+        let path = test_path("loam-chunks.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"first")?;
+        let b = writer.push(&[1u8, 2, 3])?;
+        writer.checkpoint(b)?;
+        let reader = Reader::new(&path)?;
+        let chunks = reader.chunks().collect::<Result<Vec<_>>>()?;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].id(), a.erase());
+        assert_eq!(chunks[0].len(), 6);
+        assert!(!chunks[0].is_checkpoint());
+        assert_eq!(chunks[1].id(), b.erase());
+        assert_eq!(chunks[1].data(), &[1, 2, 3]);
+        assert_eq!(chunks[2].root(), Some(b.erase()));
+        Ok(())
+    }
+
+    #[test]
+    fn lookup_borrowed() -> Result<()> {
+        #[derive(Deserialize, Serialize)]
+        struct Named<'a> {
+            name: &'a str,
+            value: u32,
+        }
+        let path = test_path("loam-borrowed.loam");
+        let mut writer = Writer::new(&path)?;
+        let named = Named {
+            name: "borrowed",
+            value: 42,
+        };
+        let id = writer.push(&named)?;
+        writer.checkpoint(id)?;
+        let reader = Reader::new(&path)?;
+        let named: Named = reader.lookup_borrowed(reader.root()?.cast())?;
+        assert_eq!(named.name, "borrowed");
+        assert_eq!(named.value, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn from_static() -> Result<()> {
+        // legacy file with a string chunk and checkpoint
+        static BUF: &[u8] = b"loam0000\x03\x02hi\x08\x08\0\0\0\0\0\0\0";
+        let reader = Reader::from_static(BUF)?;
+        assert_eq!(reader.root()?, Id::new(8));
+        assert_eq!(
+            reader.lookup_borrowed::<&str>(reader.root()?.cast())?,
+            "hi"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn refresh() -> Result<()> {
+        let path = test_path("loam-refresh.loam");
+        let mut writer = Writer::new(&path)?;
+        let mut reader = Reader::new(&path)?;
+        assert!(!reader.refresh()?);
+        let a = writer.push(&"first")?;
+        assert!(!reader.refresh()?);
+        writer.checkpoint(a)?;
+        assert!(reader.refresh()?);
+        assert_eq!(reader.lookup::<String>(reader.root()?.cast())?, "first");
+        let b = writer.push(&"second")?;
+        writer.checkpoint(b)?;
+        writer.push(&"uncommitted")?;
+        assert!(reader.poll(Duration::ZERO, Duration::ZERO)?);
+        assert_eq!(reader.lookup::<String>(reader.root()?.cast())?, "second");
+        assert!(!reader.poll(Duration::from_millis(1), Duration::ZERO)?);
+        Ok(())
+    }
+
+    #[test]
+    fn limits() -> Result<()> {
+        let mut writer = Writer::with_storage(Vec::new(), Header::default())?;
+        writer.set_compress_min(None);
+        let id = writer.push(&vec![0xAAu8; 100])?;
+        writer.checkpoint(id)?;
+        let mut reader = Reader::from_bytes(writer.into_storage())?;
+        reader.set_max_chunk_len(Some(50));
+        assert!(matches!(reader.lookup(id), Err(Error::LimitExceeded(_))));
+        reader.set_max_chunk_len(None);
+        reader.set_max_alloc(Some(50));
+        assert!(matches!(reader.lookup(id), Err(Error::LimitExceeded(_))));
+        reader.set_max_alloc(Some(200));
+        assert_eq!(reader.lookup(id)?.len(), 100);
+        // hostile length prefix (2^40 bytes)
+        let mut writer = Writer::with_storage(Vec::new(), Header::default())?;
+        let id = writer.push(&[0xFFu8, 0, 0, 0, 0, 0, 1, 0, 0])?;
+        writer.checkpoint(id)?;
+        let mut reader = Reader::from_bytes(writer.into_storage())?;
+        reader.set_max_alloc(Some(200));
+        assert!(matches!(
+            reader.lookup::<Vec<u8>>(id.cast()),
+            Err(Error::Bincode(_))
+        ));
+        // hostile chunk length prefix, overflowing with checksum
+        let header = Header::default().with_checksum(Checksum::Crc32);
+        let mut writer = Writer::with_storage(Vec::new(), header)?;
+        writer.set_compress_min(None);
+        let id = writer.push(&[0u8; 20])?;
+        writer.checkpoint(id)?;
+        let mut buf = writer.into_storage();
+        assert_eq!(id.to_usize(), 16);
+        buf[16] = 0;
+        buf[17] = 253;
+        buf[18..26].copy_from_slice(&(u64::MAX - 27).to_le_bytes());
+        let reader = Reader::from_bytes(buf)?;
+        assert!(matches!(
+            reader.lookup::<Vec<u8>>(id.cast()),
+            Err(Error::InvalidId(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn index() -> Result<()> {
+        let path = test_path("loam-index.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&vec![1u64, 2, 3])?;
+        writer.checkpoint(a)?;
+        let mut reader = Reader::new(&path)?;
+        let inside: Id<Vec<u64>> = Id::new(a.to_usize() as u64 + 2).cast();
+        assert!(!matches!(reader.lookup(inside), Err(Error::InvalidId(_))));
+        reader.build_index()?;
+        assert_eq!(reader.lookup(a)?, [1, 2, 3]);
+        assert!(matches!(reader.lookup(inside), Err(Error::InvalidId(_))));
+        let b = writer.push(&vec![4u64])?;
+        writer.checkpoint(b)?;
+        assert!(reader.refresh()?);
+        assert_eq!(reader.lookup(b)?, [4]);
+        Ok(())
     }
 }
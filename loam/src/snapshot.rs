@@ -0,0 +1,124 @@
+// snapshot.rs    Snapshot module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::common::{Error, Id, Result};
+use crate::reader::Reader;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+/// Checkpoint in a __loam__ file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// Checkpoint chunk `Id`
+    id: Id,
+
+    /// Root chunk `Id`
+    root: Id,
+
+    /// Commit record `Id` (checkpoints with metadata only)
+    commit: Option<Id>,
+
+    /// Running digest of previous chunks (integrity only)
+    digest: Option<[u8; 32]>,
+}
+
+/// Point-in-time view of a __loam__ file
+///
+/// Only chunks written before the checkpoint are visible.
+pub struct Snapshot<'a> {
+    /// Reader for file
+    reader: &'a Reader,
+
+    /// Checkpoint of snapshot
+    checkpoint: Checkpoint,
+}
+
+impl Checkpoint {
+    /// Create a new checkpoint
+    pub(crate) fn new(id: Id, root: Id, digest: Option<[u8; 32]>) -> Self {
+        Checkpoint {
+            id,
+            root,
+            commit: None,
+            digest,
+        }
+    }
+
+    /// Set the commit record `Id`
+    pub(crate) fn with_commit(mut self, commit: Id) -> Self {
+        self.commit = Some(commit);
+        self
+    }
+
+    /// Get the `Id` of the checkpoint chunk
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Get the root chunk `Id`
+    pub fn root(&self) -> Id {
+        self.root
+    }
+
+    /// Get the running digest of all chunks before the checkpoint
+    ///
+    /// This is only present if integrity hashes are enabled in the header.
+    /// It is not verified unless [Reader::verify] succeeds.
+    pub fn digest(&self) -> Option<[u8; 32]> {
+        self.digest
+    }
+
+    /// Get the commit record `Id` (checkpoints with metadata only)
+    pub(crate) fn commit(&self) -> Option<Id> {
+        self.commit
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// Create a new snapshot
+    pub(crate) fn new(reader: &'a Reader, checkpoint: Checkpoint) -> Self {
+        Snapshot { reader, checkpoint }
+    }
+
+    /// Get the snapshot checkpoint
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.checkpoint
+    }
+
+    /// Get the root chunk `Id`
+    pub fn root(&self) -> Id {
+        self.checkpoint.root
+    }
+
+    /// Lookup data for the given chunk `Id`
+    ///
+    /// Chunks written after the checkpoint are not visible.
+    pub fn lookup<D>(&self, id: Id<D>) -> Result<D>
+    where
+        D: DeserializeOwned,
+    {
+        self.check_id(id)?;
+        self.reader.lookup(id)
+    }
+
+    /// Lookup borrowed data for the given chunk `Id`
+    ///
+    /// Chunks written after the checkpoint are not visible.
+    pub fn lookup_borrowed<D>(&self, id: Id<D>) -> Result<D>
+    where
+        D: Deserialize<'a>,
+    {
+        self.check_id(id)?;
+        self.reader.lookup_borrowed(id)
+    }
+
+    /// Check that an `Id` is before the checkpoint
+    fn check_id<D>(&self, id: Id<D>) -> Result<()> {
+        if id.to_usize() < self.checkpoint.id.to_usize() {
+            Ok(())
+        } else {
+            Err(Error::InvalidId(id.erase()))
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// storage.rs    Storage module.
+//
+// Copyright (c) 2026  Douglas P Lau
+//
+use crate::common::{Error, Result};
+use crate::reader::Reader;
+use memmap2::Mmap;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Append-only storage for a [Writer](crate::Writer)
+///
+/// Implemented for `File` and in-memory `Vec<u8>` buffers.
+pub trait Storage {
+    /// Get the current length in bytes
+    fn len(&self) -> Result<u64>;
+
+    /// Check if the storage is empty
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Append bytes to the end
+    fn append(&mut self, buf: &[u8]) -> Result<()>;
+
+    /// Synchronize appended bytes to durable storage
+    fn sync(&mut self) -> Result<()>;
+
+    /// Create a reader for the current contents
+    fn reader(&self) -> Result<Reader>;
+
+    /// Get the current contents, if they are in memory
+    fn bytes(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl Storage for File {
+    fn len(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn append(&mut self, buf: &[u8]) -> Result<()> {
+        Ok(self.write_all(buf)?)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(self.sync_data()?)
+    }
+
+    fn reader(&self) -> Result<Reader> {
+        Reader::from_file(self.try_clone()?)
+    }
+}
+
+impl Storage for Vec<u8> {
+    fn len(&self) -> Result<u64> {
+        Ok(Vec::len(self) as u64)
+    }
+
+    fn append(&mut self, buf: &[u8]) -> Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn reader(&self) -> Result<Reader> {
+        Reader::from_bytes(self.as_slice())
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+/// Map result of locking a file
+fn map_lock(res: std::result::Result<(), TryLockError>) -> Result<()> {
+    match res {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(Error::Locked),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Acquire a shared lock on a file, without blocking
+pub(crate) fn lock_shared(file: &File) -> Result<()> {
+    map_lock(file.try_lock_shared())
+}
+
+/// Acquire an exclusive lock on a file, without blocking
+pub(crate) fn lock_exclusive(file: &File) -> Result<()> {
+    map_lock(file.try_lock())
+}
+
+/// Acquire the writer lock for a file
+///
+/// Readers hold shared locks on the file itself, so writers lock a separate
+/// `.lock` file.  This allows readers to follow a file while it is written.
+/// The returned lock file must be kept open while writing.
+///
+/// The `.lock` file is never removed, since another process could lock it
+/// between unlinking and unlocking, while a third creates a new one.
+pub(crate) fn lock_writer(path: &Path) -> Result<File> {
+    let mut lock = OsString::from(path);
+    lock.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock)?;
+    lock_exclusive(&file)?;
+    Ok(file)
+}
+
+/// Bytes for a [Reader](crate::Reader)
+pub(crate) enum Bytes {
+    /// Memory-mapped file
+    Mmap(Mmap),
+
+    /// Shared buffer
+    Shared(Arc<[u8]>),
+
+    /// Static buffer
+    Static(&'static [u8]),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mmap(mmap) => mmap,
+            Bytes::Shared(buf) => buf,
+            Bytes::Static(buf) => buf,
+        }
+    }
+}
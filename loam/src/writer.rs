@@ -2,72 +2,1382 @@
 //
 // Copyright (c) 2021-2025  Douglas P Lau
 //
-use crate::common::{CRC_SZ, Error, HEADER, Id, Result, checksum};
+use crate::chunk::{CHECKPOINT, COMMIT, COMPRESSED, PADDING, Span};
+use crate::codec::TypeTag;
+use crate::commit::Commit;
+use crate::common::{Error, Id, Result};
+use crate::header::{Encryption, Header};
+use crate::reader::{Reader, Recovery, check_tag, decode_raw};
+use crate::storage::{Storage, lock_exclusive, lock_shared, lock_writer};
 use bincode::Options;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Durability policy for checkpoints
+///
+/// Checkpoints are always appended to storage when written, but syncing them
+/// to durable storage can be deferred.  After a crash, the file may end at
+/// an earlier checkpoint (and need [Writer::recover]).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Durability {
+    /// Sync at every checkpoint
+    #[default]
+    Checkpoint,
+
+    /// Sync at every Nth checkpoint
+    Every(u32),
+
+    /// Sync at a checkpoint, if this long has elapsed since the last sync
+    ///
+    /// This groups checkpoints written close together into one sync.  Call
+    /// [Writer::tick] periodically, so that the last checkpoints before an
+    /// idle period are also synced.
+    Interval(Duration),
+
+    /// Never sync (except by calling [Writer::sync], or when dropped)
+    Never,
+}
 
 /// Writer for __loam__ files
 ///
-/// The writer can be used to create or append to an existing file.
-pub struct Writer {
-    file: File,
+/// The writer can be used to create or append to an existing file.  Other
+/// [Storage] can also be used, such as an in-memory `Vec<u8>`.
+///
+/// Chunks are buffered in memory, and appended to storage when a checkpoint
+/// is added (or the buffer fills up).  Storage is synced according to the
+/// [Durability] policy, and when the writer is dropped.  Chunks buffered
+/// after the last checkpoint are discarded when dropped.
+///
+/// Chunks can be read back with [Writer::lookup], even before a checkpoint.
+///
+/// Only one writer can have a file open at a time, enforced by an advisory
+/// lock on a separate file (with `.lock` appended to the path).
+pub struct Writer<S: Storage = File> {
+    /// Storage to append (taken only by [Writer::into_storage])
+    storage: Option<S>,
+
+    /// Writer lock file (held until dropped)
+    _lock: Option<File>,
+
+    /// Length of storage, including buffered chunks
+    len: u64,
+
+    /// Buffered chunks, not yet appended to storage
+    buffer: Vec<u8>,
+
+    /// Durability policy
+    durability: Durability,
+
+    /// Number of checkpoints since last sync
+    unsynced: u32,
+
+    /// Time of last sync
+    synced: Instant,
+
+    /// Reader for chunks written so far
+    reader: Option<Reader>,
+
+    /// File header
+    header: Header,
+
+    /// Minimum data length for compression
+    compress_min: Option<usize>,
+
+    /// Sequence number of last checkpoint with metadata
+    seq: u64,
+
+    /// Running digest of all chunks (integrity only)
+    digest: [u8; 32],
+
+    /// Key for encrypting chunks
+    encryption_key: Option<[u8; 32]>,
+
+    /// Key for signing checkpoints
+    #[cfg(feature = "sign")]
+    signing_key: Option<ed25519_dalek::SigningKey>,
+
+    /// Chunk `Id`s indexed by data hash (deduplication only)
+    dedup: Option<HashMap<u64, Vec<Id>>>,
 }
 
-impl Writer {
+/// Default minimum data length for compression
+const COMPRESS_MIN: usize = 64;
+
+/// Buffer length for flushing chunks to storage
+const BUFFER_LEN: usize = 64 * 1024;
+
+/// Calculate hash of chunk data
+fn data_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Writer<File> {
     /// Create a new Writer
+    ///
+    /// The file must not already exist.  It is created with a default
+    /// header, which depends on enabled features.
     pub fn new<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let mut file = OpenOptions::new()
+        Self::with_header(path, Header::default())
+    }
+
+    /// Create a new Writer with the given header
+    ///
+    /// The file must not already exist.  The writer lock is held on a
+    /// separate file (the path with `.lock` appended), which is left in
+    /// place after the writer is dropped.
+    pub fn with_header<P>(path: P, header: Header) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
             .create_new(true)
+            .read(true)
             .append(true)
             .open(path)?;
-        let len = file.metadata()?.len();
-        if len > 0 && len < 8 {
+        let lock = lock_writer(path)?;
+        lock_shared(&file)?;
+        let mut writer = Self::with_storage(file, header)?;
+        writer._lock = Some(lock);
+        Ok(writer)
+    }
+
+    /// Open a Writer to append to an existing file
+    ///
+    /// The file must have a valid header, and end with a checkpoint (unless
+    /// no chunks have been written yet).  New chunks are written using the
+    /// options recorded in the header.
+    ///
+    /// If another writer has the file open, [Error::Locked] is returned.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = OpenOptions::new().read(true).append(true).open(path)?;
+        let lock = lock_writer(path)?;
+        lock_shared(&file)?;
+        let reader = Reader::from_file(file.try_clone()?)?;
+        let header = *reader.header();
+        let mut seq = 0;
+        if file.metadata()?.len() > header.len() as u64 {
+            let checkpoint = reader.checkpoint()?;
+            let mut meta = reader.metadata(checkpoint)?;
+            if meta.is_none() {
+                // walk back to newest checkpoint with metadata
+                for checkpoint in reader.checkpoints()? {
+                    meta = reader.metadata(checkpoint)?;
+                    if meta.is_some() {
+                        break;
+                    }
+                }
+            }
+            if let Some(meta) = meta {
+                seq = meta.seq();
+            }
+        }
+        let mut writer = Self::from_storage(file, header)?;
+        writer._lock = Some(lock);
+        writer.seq = seq;
+        writer.digest = reader.digest()?;
+        Ok(writer)
+    }
+
+    /// Recover an existing file after a crash
+    ///
+    /// All chunks are scanned to find the last valid checkpoint.  Any torn
+    /// data after that point is truncated, so the file can be reopened with
+    /// [Writer::open].
+    ///
+    /// An exclusive lock is required, so [Error::Locked] is returned if any
+    /// readers or writers have the file open.
+    pub fn recover<P>(path: P) -> Result<Recovery>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        lock_exclusive(&file)?;
+        let recovery = Reader::from_file(file.try_clone()?)?.recovery();
+        if recovery.is_torn() {
+            file.set_len(recovery.valid_len())?;
+            file.sync_all()?;
+        }
+        Ok(recovery)
+    }
+}
+
+impl<S: Storage> Writer<S> {
+    /// Create a new Writer with empty storage
+    ///
+    /// The header is appended to the storage.  No locks are acquired, even
+    /// if the storage is a `File`.
+    pub fn with_storage(mut storage: S, header: Header) -> Result<Self> {
+        header.check_supported()?;
+        if !storage.is_empty()? {
             return Err(Error::InvalidHeader);
         }
-        if len == 0 {
-            file.write_all(HEADER)?;
+        let buf = header.encode()?;
+        storage.append(&buf)?;
+        let mut writer = Self::from_storage(storage, header)?;
+        header.integrity().fold(&mut writer.digest, &buf);
+        Ok(writer)
+    }
+
+    /// Create a Writer for existing storage
+    fn from_storage(storage: S, header: Header) -> Result<Self> {
+        Ok(Self {
+            len: storage.len()?,
+            storage: Some(storage),
+            _lock: None,
+            buffer: Vec::new(),
+            durability: Durability::default(),
+            unsynced: 0,
+            synced: Instant::now(),
+            reader: None,
+            header,
+            compress_min: Some(COMPRESS_MIN),
+            seq: 0,
+            digest: [0; 32],
+            encryption_key: None,
+            #[cfg(feature = "sign")]
+            signing_key: None,
+            dedup: None,
+        })
+    }
+
+    /// Get the storage
+    ///
+    /// Chunks after the last checkpoint may still be buffered (see
+    /// [Writer::flush]).
+    pub fn storage(&self) -> &S {
+        self.storage.as_ref().expect("storage")
+    }
+
+    /// Consume the Writer, returning its storage
+    ///
+    /// Chunks after the last checkpoint may still be buffered (see
+    /// [Writer::flush]).
+    pub fn into_storage(mut self) -> S {
+        self.storage.take().expect("storage")
+    }
+
+    /// Get the file header
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Set the minimum data length for compression.
+    ///
+    /// Chunks with less data are not compressed, nor are chunks which would
+    /// not be smaller after compression.  `None` disables compression.
+    ///
+    /// This has no effect unless compression is enabled in the header.
+    pub fn set_compress_min(&mut self, min_len: Option<usize>) {
+        self.compress_min = min_len;
+    }
+
+    /// Set the durability policy for checkpoints.
+    ///
+    /// The default is [Durability::Checkpoint].
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    /// Enable or disable deduplication.
+    ///
+    /// When enabled, pushing data which is identical to an existing chunk
+    /// returns the `Id` of that chunk, instead of appending a new one.  All
+    /// chunks already in the storage are scanned to build a hash index.
+    /// Matching hashes are verified by comparing serialized data.
+    pub fn set_dedup(&mut self, dedup: bool) -> Result<()> {
+        self.dedup = None;
+        if dedup {
+            let reader = self.reader()?;
+            let mut ids = Vec::new();
+            let mut commits = HashSet::new();
+            for chunk in reader.chunks() {
+                let chunk = chunk?;
+                match chunk.checkpoint() {
+                    Some(checkpoint) => commits.extend(checkpoint.commit()),
+                    None => ids.push(chunk.id()),
+                }
+            }
+            let mut index: HashMap<u64, Vec<Id>> = HashMap::new();
+            for id in ids.into_iter().filter(|id| !commits.contains(id)) {
+                let data = reader.lookup_raw(id)?;
+                index.entry(data_hash(&data)).or_default().push(id);
+            }
+            self.dedup = Some(index);
         }
-        Ok(Self { file })
+        Ok(())
+    }
+
+    /// Set the key for encrypting chunks.
+    ///
+    /// This is required if encryption is enabled in the header, or else
+    /// pushing a chunk returns [Error::MissingKey].
+    pub fn set_encryption_key(&mut self, key: [u8; 32]) {
+        self.encryption_key = Some(key);
+        self.reader = None;
+    }
+
+    /// Set the key for signing checkpoints.
+    ///
+    /// This is required if signatures are enabled in the header, or else
+    /// adding a checkpoint returns [Error::MissingKey].
+    #[cfg(feature = "sign")]
+    pub fn set_signing_key(&mut self, key: ed25519_dalek::SigningKey) {
+        self.signing_key = Some(key);
     }
 
     /// Push a chunk of data to the end of the file.
     ///
+    /// If deduplication is enabled, an existing chunk may be returned.
+    ///
+    /// # Returns
+    /// `Id` chunk identifier, typed by the data
+    pub fn push<D>(&mut self, data: &D) -> Result<Id<D>>
+    where
+        D: Serialize,
+    {
+        self.push_with_tag(data, 0)
+    }
+
+    /// Push a chunk of data with a type tag to the end of the file.
+    ///
+    /// The tag is only written if type tags are enabled in the header.
+    ///
     /// # Returns
-    /// `Id` chunk identifier
-    pub fn push<D>(&mut self, data: &D) -> Result<Id>
+    /// `Id` chunk identifier, typed by the data
+    pub fn push_tagged<D>(&mut self, data: &D) -> Result<Id<D>>
+    where
+        D: Serialize + TypeTag,
+    {
+        self.push_with_tag(data, D::TAG)
+    }
+
+    /// Push a chunk of data with a type tag (if enabled)
+    pub(crate) fn push_with_tag<D>(
+        &mut self,
+        data: &D,
+        tag: u32,
+    ) -> Result<Id<D>>
     where
         D: Serialize,
     {
-        let len = self.file.metadata()?.len();
-        let id = Id::new(len);
+        let mut data = self.header.codec().serialize(data)?;
+        if self.header.type_tags() {
+            data.splice(0..0, tag.to_le_bytes());
+        }
+        Ok(self.push_raw(&data)?.cast())
+    }
+
+    /// Push a chunk of raw (serialized) data to the end of the file.
+    pub(crate) fn push_raw(&mut self, data: &[u8]) -> Result<Id> {
+        let Some(index) = &self.dedup else {
+            return self.append_raw(data);
+        };
+        let hash = data_hash(data);
+        if let Some(ids) = index.get(&hash).cloned() {
+            for id in ids {
+                if *self.lookup_raw(id)? == *data {
+                    return Ok(id);
+                }
+            }
+        }
+        let id = self.append_raw(data)?;
+        if let Some(index) = &mut self.dedup {
+            index.entry(hash).or_default().push(id);
+        }
+        Ok(id)
+    }
+
+    /// Lookup raw (serialized) data for a chunk `Id` which has been pushed
+    ///
+    /// Chunks which are still buffered are decoded without flushing, and
+    /// storage is only remapped if the chunk is not already mapped.
+    fn lookup_raw(&mut self, id: Id) -> Result<Cow<'_, [u8]>> {
+        let key = self.encryption_key;
+        let flushed = self.len as usize - self.buffer.len();
+        if id.to_usize() < flushed {
+            let mapped = self.storage().bytes().is_some()
+                || self
+                    .reader
+                    .as_ref()
+                    .is_some_and(|r| id.to_usize() < r.len());
+            if !mapped {
+                self.reader()?;
+            }
+            if let Some(bytes) = self.storage().bytes() {
+                let span = Span::decode(bytes, &self.header, id)?;
+                return decode_raw(
+                    &self.header,
+                    key.as_ref(),
+                    None,
+                    bytes,
+                    &span,
+                );
+            }
+            if let Some(reader) = &self.reader {
+                return reader.lookup_raw(id);
+            }
+        }
+        let span = Span::decode_at(&self.buffer, flushed, &self.header, id)?;
+        decode_raw(&self.header, key.as_ref(), None, &self.buffer, &span)
+    }
+
+    /// Append a chunk of raw data, compressing it if possible.
+    fn append_raw(&mut self, data: &[u8]) -> Result<Id> {
+        let mut flags = 0;
+        let mut data = Cow::Borrowed(data);
+        if let Some(min_len) = self.compress_min
+            && data.len() >= min_len
+            && let Some(packed) = self.header.compression().compress(&data)
+            && packed.len() < data.len()
+        {
+            flags = COMPRESSED;
+            data = Cow::Owned(packed);
+        }
+        let encryption = self.header.encryption();
+        if encryption != Encryption::None {
+            let key = self.encryption_key.ok_or(Error::MissingKey)?;
+            // align first, since the nonce depends on the `Id`
+            self.align(self.chunk_len(data.len() + encryption.overhead())?)?;
+            let id = Id::new(self.len);
+            let sealed = encryption
+                .encrypt(&key, id, &data)
+                .ok_or(Error::UnsupportedHeader)?;
+            data = Cow::Owned(sealed);
+        }
+        self.write_chunk(flags, &data)
+    }
+
+    /// Get the total length of a chunk
+    fn chunk_len(&self, data_len: usize) -> Result<usize> {
+        let options = bincode::DefaultOptions::new().with_varint_encoding();
+        Ok(self.header.flags_size()
+            + options.serialized_size(&data_len)? as usize
+            + data_len
+            + self.header.checksum().size())
+    }
+
+    /// Add padding (if needed) so a chunk will not straddle a block boundary
+    ///
+    /// Chunks larger than one block are aligned to the start of a block.
+    fn align(&mut self, chunk_len: usize) -> Result<()> {
+        let Some(block) = self.header.alignment() else {
+            return Ok(());
+        };
+        let offset = (self.len % block as u64) as usize;
+        if offset == 0 || offset + chunk_len <= block {
+            return Ok(());
+        }
+        // padding length must be exact, or else fill another block
+        let mut gap = block - offset;
+        let overhead = self.header.flags_size() + self.header.checksum().size();
+        loop {
+            // try each varint width of the length
+            for width in [1, 3, 5, 9] {
+                if let Some(len) = gap.checked_sub(overhead + width)
+                    && self.chunk_len(len)? == gap
+                {
+                    self.write_chunk(PADDING, &vec![0; len])?;
+                    return Ok(());
+                }
+            }
+            gap += block;
+        }
+    }
+
+    /// Write a chunk (flags, length and data), appending a checksum.
+    ///
+    /// The chunk is buffered, and the buffer is flushed if full.  Padding
+    /// is added first if needed for alignment.
+    fn write_chunk(&mut self, flags: u8, data: &[u8]) -> Result<Id> {
+        if flags & PADDING == 0 {
+            self.align(self.chunk_len(data.len())?)?;
+        }
+        let id = Id::new(self.len);
         let options = bincode::DefaultOptions::new()
             .with_little_endian()
             .with_varint_encoding();
-        let len = options.serialized_size(data)? as usize;
-        let lenlen = options.serialized_size(&len)? as usize;
-        let mut buf = Vec::with_capacity(lenlen + len + CRC_SZ);
-        options.serialize_into(&mut buf, &len)?;
-        options.serialize_into(&mut buf, &data)?;
-        if let Some(checksum) = checksum(&buf) {
-            buf.extend(checksum.to_le_bytes());
-        }
-        self.file.write_all(&buf)?;
+        let start = self.buffer.len();
+        let flags_sz = self.header.flags_size();
+        self.buffer.extend_from_slice(&[flags][..flags_sz]);
+        options.serialize_into(&mut self.buffer, &data.len())?;
+        self.buffer.extend_from_slice(data);
+        let checksum = self.header.checksum().calculate(&self.buffer[start..]);
+        if let Some(checksum) = checksum {
+            self.buffer.extend(checksum.to_le_bytes());
+        }
+        let chunk = &self.buffer[start..];
+        if flags & PADDING == 0 {
+            self.header.integrity().fold(&mut self.digest, chunk);
+        }
+        self.len += chunk.len() as u64;
+        if self.buffer.len() >= BUFFER_LEN {
+            self.flush()?;
+        }
         Ok(id)
     }
 
+    /// Flush buffered chunks to storage.
+    ///
+    /// This is done automatically when adding a checkpoint.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.buffer.is_empty() {
+            let storage = self.storage.as_mut().expect("storage");
+            storage.append(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Flush buffered chunks and sync storage.
+    ///
+    /// This makes all checkpoints durable, regardless of the [Durability]
+    /// policy.
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.sync_storage()
+    }
+
+    /// Sync checkpoints if the [Durability::Interval] has elapsed.
+    ///
+    /// The writer has no timer of its own, so this should be called
+    /// periodically (from an event loop, for example) when using that
+    /// policy.  Chunks buffered after the last checkpoint are not flushed.
+    pub fn tick(&mut self) -> Result<()> {
+        if let Durability::Interval(interval) = self.durability
+            && self.unsynced > 0
+            && self.synced.elapsed() >= interval
+        {
+            self.sync_storage()?;
+        }
+        Ok(())
+    }
+
+    /// Sync storage, without flushing buffered chunks
+    fn sync_storage(&mut self) -> Result<()> {
+        self.storage.as_mut().expect("storage").sync()?;
+        self.unsynced = 0;
+        self.synced = Instant::now();
+        Ok(())
+    }
+
+    /// Flush a checkpoint, syncing storage according to durability policy
+    fn flush_checkpoint(&mut self) -> Result<()> {
+        self.flush()?;
+        self.unsynced += 1;
+        let sync = match self.durability {
+            Durability::Checkpoint => true,
+            Durability::Every(n) => self.unsynced >= n,
+            Durability::Interval(interval) => self.synced.elapsed() >= interval,
+            Durability::Never => false,
+        };
+        if sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Get checkpoint data for an `Id`, with running digest and signature
+    #[cfg_attr(not(feature = "sign"), allow(unused_variables))]
+    fn checkpoint_data(&self, flags: u8, id: Id) -> Result<Vec<u8>> {
+        let mut data = id.to_le_bytes().to_vec();
+        data.extend_from_slice(&self.digest[..self.header.integrity().size()]);
+        #[cfg(feature = "sign")]
+        if self.header.signature() == crate::header::Signature::Ed25519 {
+            use ed25519_dalek::Signer;
+            let key = self.signing_key.as_ref().ok_or(Error::MissingKey)?;
+            // the signature also covers the chunk flags
+            let msg = [&[flags][..], &data].concat();
+            data.extend(key.sign(&msg).to_bytes());
+        }
+        Ok(data)
+    }
+
+    /// Get a reader for all chunks written so far.
+    ///
+    /// Buffered chunks are flushed, and the storage is remapped only if
+    /// chunks have been pushed since the last call.  Chunks after the last
+    /// checkpoint can be looked up, but the reader's `root` is only valid if
+    /// the storage ends with a checkpoint.
+    pub fn reader(&mut self) -> Result<&Reader> {
+        self.flush()?;
+        let reader = match self.reader.take() {
+            Some(reader) if reader.len() as u64 == self.len => reader,
+            _ => {
+                let mut reader = self.storage().reader()?;
+                if let Some(key) = self.encryption_key {
+                    reader.set_encryption_key(key);
+                }
+                reader
+            }
+        };
+        Ok(self.reader.insert(reader))
+    }
+
+    /// Lookup data for a chunk `Id` which has been pushed
+    ///
+    /// Buffered chunks are decoded without flushing them to storage.
+    pub fn lookup<D>(&mut self, id: Id<D>) -> Result<D>
+    where
+        D: DeserializeOwned,
+    {
+        self.lookup_with_tag(id, 0)
+    }
+
+    /// Lookup data with a type tag for a chunk `Id` which has been pushed
+    ///
+    /// If type tags are enabled in the header, the chunk tag must match, or
+    /// [Error::TypeMismatch] is returned.
+    pub fn lookup_tagged<D>(&mut self, id: Id<D>) -> Result<D>
+    where
+        D: DeserializeOwned + TypeTag,
+    {
+        self.lookup_with_tag(id, D::TAG)
+    }
+
+    /// Lookup data for a chunk `Id`, checking its type tag
+    fn lookup_with_tag<D>(&mut self, id: Id<D>, tag: u32) -> Result<D>
+    where
+        D: DeserializeOwned,
+    {
+        let header = self.header;
+        let data = self.lookup_raw(id.erase())?;
+        let data = check_tag(&header, id.erase(), &data, tag)?;
+        header.codec().deserialize(data, None)
+    }
+
     /// Add a checkpoint to the file.  The `Id` commonly points to the root of a
     /// tree of nodes.
     ///
     /// In order to be read back, a file must end with a checkpoint.
-    pub fn checkpoint(&mut self, id: Id) -> Result<()> {
-        self.push(&id.to_le_bytes())?;
-        self.file.sync_data()?;
+    pub fn checkpoint<T>(&mut self, id: Id<T>) -> Result<()> {
+        let data = self.checkpoint_data(CHECKPOINT, id.erase())?;
+        self.write_chunk(CHECKPOINT, &data)?;
+        self.flush_checkpoint()
+    }
+
+    /// Add a checkpoint with metadata to the file.
+    ///
+    /// The metadata contains a sequence number (one more than the previous
+    /// checkpoint with metadata), the current time and a serialized
+    /// `payload`.  It can be read with [Reader::metadata].
+    ///
+    /// Legacy files do not support metadata.
+    pub fn checkpoint_with<T, P>(
+        &mut self,
+        id: Id<T>,
+        payload: &P,
+    ) -> Result<()>
+    where
+        P: Serialize,
+    {
+        let payload = self.header.codec().serialize(payload)?;
+        self.commit(id.erase(), Vec::new(), payload)
+    }
+
+    /// Add a checkpoint with multiple named roots to the file.
+    ///
+    /// All roots are committed atomically, and can be read with
+    /// [Reader::root_named].  The checkpoint also has metadata (with an
+    /// empty payload), but its unnamed root is zero.
+    ///
+    /// Legacy files do not support named roots.
+    pub fn checkpoint_named<'n, I>(&mut self, roots: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'n str, Id)>,
+    {
+        let roots = roots
+            .into_iter()
+            .map(|(name, id)| (name.to_string(), id))
+            .collect();
+        let payload = self.header.codec().serialize(&())?;
+        self.commit(Id::new(0), roots, payload)
+    }
+
+    /// Write a commit record and checkpoint
+    fn commit(
+        &mut self,
+        root: Id,
+        roots: Vec<(String, Id)>,
+        payload: Vec<u8>,
+    ) -> Result<()> {
+        if self.header.is_legacy() {
+            return Err(Error::UnsupportedHeader);
+        }
+        let commit = Commit::new(self.seq + 1, root, roots, payload);
+        let cid = self.write_chunk(0, &commit.encode()?)?;
+        let flags = CHECKPOINT | COMMIT;
+        let data = self.checkpoint_data(flags, cid)?;
+        self.write_chunk(flags, &data)?;
+        self.flush_checkpoint()?;
+        self.seq = commit.seq;
+        Ok(())
+    }
+}
+
+impl<S: Storage> Drop for Writer<S> {
+    /// Sync any checkpoints which are not yet durable
+    ///
+    /// Chunks buffered after the last checkpoint are discarded, so the
+    /// storage still ends with a checkpoint.  Errors are ignored; use
+    /// [Writer::sync] to check them.
+    fn drop(&mut self) {
+        if self.unsynced > 0
+            && let Some(storage) = &mut self.storage
+        {
+            let _ = storage.sync();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::test_path;
+    use crate::header::Checksum;
+    use serde::{Deserialize, Serialize};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn append() -> Result<()> {
+        let path = test_path("loam-append.loam");
+        let mut writer = Writer::new(&path)?;
+        let id = writer.push(&"first")?;
+        writer.checkpoint(id)?;
+        drop(writer);
+        let mut writer = Writer::open(&path)?;
+        let id = writer.push(&"second")?;
+        writer.checkpoint(id)?;
+        let reader = Reader::new(&path)?;
+        let msg: String = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(msg, "second");
+        Ok(())
+    }
+
+    #[test]
+    fn open_torn() -> Result<()> {
+        let path = test_path("loam-open-torn.loam");
+        let mut writer = Writer::new(&path)?;
+        let id = writer.push(&"first")?;
+        writer.checkpoint(id)?;
+        writer.push(&"torn")?;
+        writer.flush()?;
+        drop(writer);
+        assert!(matches!(Writer::open(&path), Err(Error::InvalidCheckpoint)));
+        Ok(())
+    }
+
+    #[test]
+    fn recover() -> Result<()> {
+        let path = test_path("loam-recover.loam");
+        let mut writer = Writer::new(&path)?;
+        let id = writer.push(&"first")?;
+        writer.checkpoint(id)?;
+        let id = writer.push(&"second")?;
+        writer.checkpoint(id)?;
+        let len = std::fs::metadata(&path)?.len();
+        writer.push(&"torn")?;
+        writer.storage.as_mut().unwrap().append(&[0xFF; 3])?;
+        drop(writer);
+        let recovery = Writer::recover(&path)?;
+        assert!(recovery.is_torn());
+        assert_eq!(recovery.valid_len(), len);
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.root()?, recovery.root().unwrap());
+        let msg: String = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(msg, "second");
+        drop(reader);
+        assert!(!Writer::recover(&path)?.is_torn());
+        Ok(())
+    }
+
+    #[test]
+    fn recover_legacy_torn() -> Result<()> {
+        // legacy file with CRCs, three checkpoints and a torn tail
+        fn chunk(buf: &mut Vec<u8>, data: &[u8]) -> Id {
+            let id = Id::from_usize(buf.len());
+            let start = buf.len();
+            buf.push(data.len() as u8);
+            buf.extend_from_slice(data);
+            let crc = Checksum::Crc32.calculate(&buf[start..]).unwrap();
+            buf.extend(crc.to_le_bytes());
+            id
+        }
+        let mut buf = b"loam0000".to_vec();
+        let mut root = Id::new(0);
+        for msg in [b"\x02hi", b"\x02ho", b"\x02ha"] {
+            root = chunk(&mut buf, msg);
+            chunk(&mut buf, &root.to_le_bytes());
+        }
+        let len = buf.len() as u64;
+        buf.extend(b"\x05abc");
+        let path = test_path("loam-recover-legacy.loam");
+        std::fs::write(&path, &buf)?;
+        let recovery = Writer::recover(&path)?;
+        assert_eq!(recovery.valid_len(), len);
+        assert_eq!(recovery.root(), Some(root));
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.header().checksum(), Checksum::Crc32);
+        let msg: String = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(msg, "ha");
+        Ok(())
+    }
+
+    #[test]
+    fn checksum() -> Result<()> {
+        let path = test_path("loam-checksum.loam");
+        let header = Header::default().with_checksum(Checksum::Crc32);
+        let mut writer = Writer::with_header(&path, header)?;
+        let id = writer.push(&"checked")?;
+        writer.checkpoint(id)?;
+        drop(writer);
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.header().checksum(), Checksum::Crc32);
+        let msg: String = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(msg, "checked");
+        let mut buf = std::fs::read(&path)?;
+        buf[id.to_usize() + 4] ^= 0xFF;
+        std::fs::write(&path, buf)?;
+        let reader = Reader::new(&path)?;
+        assert!(matches!(
+            reader.lookup::<String>(id.cast()),
+            Err(Error::InvalidCrc(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn compress() -> Result<()> {
+        use crate::header::Compression;
+        let path = test_path("loam-compress.loam");
+        let header = Header::default().with_compression(Compression::Lz4);
+        let mut writer = Writer::with_header(&path, header)?;
+        let long = "compressible ".repeat(100);
+        let a = writer.push(&long)?;
+        let b = writer.push(&"short")?;
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        let chunks = reader.chunks().collect::<Result<Vec<_>>>()?;
+        assert!(chunks[0].is_compressed());
+        assert!(chunks[0].len() < long.len());
+        assert!(!chunks[1].is_compressed());
+        let msg: String = reader.lookup(a)?;
+        assert_eq!(msg, long);
+        assert!(matches!(
+            reader.lookup_borrowed::<&str>(a.cast()),
+            Err(Error::InvalidBorrow(_))
+        ));
+        assert_eq!(reader.lookup_borrowed::<&str>(b)?, "short");
+        Ok(())
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard() -> Result<()> {
+        use crate::codec::Codec;
+        let path = test_path("loam-postcard.loam");
+        let header = Header::default().with_codec(Codec::Postcard);
+        let mut writer = Writer::with_header(&path, header)?;
+        let id = writer.push(&(7u32, "postcard"))?;
+        writer.checkpoint(id)?;
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.header().codec(), Codec::Postcard);
+        let data: (u32, String) = reader.lookup(id.cast())?;
+        assert_eq!(data, (7, "postcard".to_string()));
+        if !reader.chunks().next().unwrap()?.is_compressed() {
+            let data: (u32, &str) = reader.lookup_borrowed(id)?;
+            assert_eq!(data, (7, "postcard"));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor() -> Result<()> {
+        use crate::codec::Codec;
+        let path = test_path("loam-cbor.loam");
+        let header = Header::default().with_codec(Codec::Cbor);
+        let mut writer = Writer::with_header(&path, header)?;
+        let id = writer.push(&(7u32, "cbor"))?;
+        writer.checkpoint(id)?;
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.header().codec(), Codec::Cbor);
+        let data: (u32, String) = reader.lookup(id.cast())?;
+        assert_eq!(data, (7, "cbor".to_string()));
+        assert!(matches!(
+            reader.lookup_borrowed::<(u32, &str)>(id),
+            Err(Error::InvalidBorrow(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn in_memory() -> Result<()> {
+        let mut writer = Writer::with_storage(Vec::new(), Header::default())?;
+        let id = writer.push(&"in memory")?;
+        writer.checkpoint(id)?;
+        let reader = Reader::from_bytes(writer.into_storage())?;
+        let msg: String = reader.lookup(reader.root()?.cast())?;
+        assert_eq!(msg, "in memory");
+        let buf = vec![0xFF];
+        assert!(matches!(
+            Writer::with_storage(buf, Header::default()),
+            Err(Error::InvalidHeader)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn read_own_writes() -> Result<()> {
+        let path = test_path("loam-read-own-writes.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"uncommitted")?;
+        let msg: String = writer.lookup(a.cast())?;
+        assert_eq!(msg, "uncommitted");
+        let b = writer.push(&vec![a])?;
+        let ids: Vec<Id<&str>> = writer.lookup(b)?;
+        assert_eq!(ids, [a]);
+        writer.checkpoint(b)?;
+        assert_eq!(writer.reader()?.root()?, b.erase());
+        let mut writer = Writer::with_storage(Vec::new(), Header::default())?;
+        let a = writer.push(&"in memory")?;
+        let msg: String = writer.lookup(a.cast())?;
+        assert_eq!(msg, "in memory");
+        Ok(())
+    }
+
+    #[test]
+    fn lock() -> Result<()> {
+        let path = test_path("loam-lock.loam");
+        let mut writer = Writer::new(&path)?;
+        let id = writer.push(&"locked")?;
+        writer.checkpoint(id)?;
+        assert!(matches!(Writer::open(&path), Err(Error::Locked)));
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.root()?, id.erase());
+        drop(writer);
+        assert!(matches!(Writer::recover(&path), Err(Error::Locked)));
+        drop(reader);
+        assert!(!Writer::recover(&path)?.is_torn());
+        Writer::open(&path)?;
+        // no lock file is created when the file already exists
+        let path = test_path("loam-lock-exists.loam");
+        let lock = test_path("loam-lock-exists.loam.lock");
+        std::fs::write(&path, b"")?;
+        assert!(matches!(Writer::new(&path), Err(Error::Io(_))));
+        assert!(!lock.exists());
+        Ok(())
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Point32(f32, f32);
+
+    impl TypeTag for Point32 {
+        const TAG: u32 = 0x5032;
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Point64(f64, f64);
+
+    impl TypeTag for Point64 {
+        const TAG: u32 = 0x5064;
+    }
+
+    #[test]
+    fn type_tags() -> Result<()> {
+        let path = test_path("loam-type-tags.loam");
+        let header = Header::default().with_type_tags(true);
+        let mut writer = Writer::with_header(&path, header)?;
+        let id = writer.push_tagged(&Point32(1.0, 2.0))?;
+        let hi = writer.push(&"hi")?;
+        writer.checkpoint(id)?;
+        let reader = Reader::new(&path)?;
+        assert!(reader.header().type_tags());
+        assert_eq!(reader.lookup_tagged(id)?, Point32(1.0, 2.0));
+        assert!(matches!(
+            reader.lookup_tagged::<Point64>(id.cast()),
+            Err(Error::TypeMismatch(_))
+        ));
+        assert!(matches!(reader.lookup(id), Err(Error::TypeMismatch(_))));
+        assert_eq!(reader.lookup::<String>(hi.cast())?, "hi");
+        assert_eq!(reader.lookup_borrowed(hi)?, "hi");
+        assert!(matches!(
+            reader.lookup_tagged::<Point32>(hi.cast()),
+            Err(Error::TypeMismatch(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata() -> Result<()> {
+        let path = test_path("loam-metadata.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"first")?;
+        writer.checkpoint_with(a, &"version 1")?;
+        drop(writer);
+        let mut writer = Writer::open(&path)?;
+        let b = writer.push(&"second")?;
+        writer.checkpoint_with(b, &(2u32, "version 2"))?;
+        let reader = Reader::new(&path)?;
+        let checkpoint = reader.checkpoint()?;
+        assert_eq!(checkpoint.root(), b.erase());
+        let meta = reader.metadata(checkpoint)?.unwrap();
+        assert_eq!(meta.seq(), 2);
+        assert!(meta.timestamp() <= std::time::SystemTime::now());
+        assert_eq!(meta.payload::<(u32, String)>()?, (2, "version 2".into()));
+        let checkpoints = reader.checkpoints()?;
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[1].root(), a.erase());
+        let meta = reader.metadata(checkpoints[1])?.unwrap();
+        assert_eq!(meta.seq(), 1);
+        assert_eq!(meta.payload::<String>()?, "version 1");
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        assert!(reader.metadata(reader.checkpoint()?)?.is_none());
+        drop(writer);
+        let mut writer = Writer::open(&path)?;
+        writer.checkpoint_with(a, &"version 3")?;
+        let reader = Reader::new(&path)?;
+        let meta = reader.metadata(reader.checkpoint()?)?.unwrap();
+        assert_eq!(meta.seq(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn named_roots() -> Result<()> {
+        let path = test_path("loam-named-roots.loam");
+        let mut writer = Writer::new(&path)?;
+        let a = writer.push(&"alpha")?.erase();
+        let b = writer.push(&"beta")?.erase();
+        writer.checkpoint_named([("a", a), ("b", b)])?;
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.root_named("a")?, Some(a));
+        assert_eq!(reader.root_named("b")?, Some(b));
+        assert_eq!(reader.root_named("c")?, None);
+        let meta = reader.metadata(reader.checkpoint()?)?.unwrap();
+        assert_eq!(meta.seq(), 1);
+        let roots: Vec<_> = meta.roots().collect();
+        assert_eq!(roots, [("a", a), ("b", b)]);
+        let msg: String = reader.lookup(b.cast())?;
+        assert_eq!(msg, "beta");
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        assert_eq!(reader.root_named("a")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn dedup() -> Result<()> {
+        let path = test_path("loam-dedup.loam");
+        let mut writer = Writer::new(&path)?;
+        writer.set_dedup(true)?;
+        let a = writer.push(&"alpha")?;
+        let b = writer.push(&"beta")?;
+        // buffered chunks are compared without flushing
+        let len = std::fs::metadata(&path)?.len();
+        assert_eq!(writer.push(&"alpha")?, a);
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        assert_ne!(a, b);
+        writer.checkpoint(b)?;
+        drop(writer);
+        let len = std::fs::metadata(&path)?.len();
+        let mut writer = Writer::open(&path)?;
+        writer.set_dedup(true)?;
+        assert_eq!(writer.push(&"beta")?, b);
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        writer.set_dedup(false)?;
+        assert_ne!(writer.push(&"beta")?, b);
+        Ok(())
+    }
+
+    #[cfg(feature = "integrity")]
+    #[test]
+    fn integrity() -> Result<()> {
+        use crate::header::Integrity;
+        let path = test_path("loam-integrity.loam");
+        let header = Header::default()
+            .with_checksum(Checksum::None)
+            .with_integrity(Integrity::Blake3)
+            .with_alignment(Some(64));
+        let mut writer = Writer::with_header(&path, header)?;
+        let a = writer.push(&"alpha")?;
+        writer.checkpoint(a)?;
+        drop(writer);
+        let mut writer = Writer::open(&path)?;
+        let b = writer.push(&"beta")?;
+        writer.checkpoint_with(b, &"payload")?;
+        let reader = Reader::new(&path)?;
+        reader.verify()?;
+        let checkpoints = reader.checkpoints()?;
+        assert_eq!(checkpoints.len(), 2);
+        assert_ne!(checkpoints[0].digest(), checkpoints[1].digest());
+        let msg: String = reader.lookup(b.cast())?;
+        assert_eq!(msg, "beta");
+        drop(reader);
+        drop(writer);
+        // tamper with the first chunk
+        let mut buf = std::fs::read(&path)?;
+        let pos = buf.windows(5).position(|w| w == b"alpha").unwrap();
+        buf[pos] = b'A';
+        let reader = Reader::from_bytes(buf)?;
+        assert!(matches!(reader.verify(), Err(Error::InvalidDigest(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "sign")]
+    #[test]
+    fn sign() -> Result<()> {
+        use crate::header::{Integrity, Signature};
+        use ed25519_dalek::SigningKey;
+        let path = test_path("loam-sign.loam");
+        let header = Header::default()
+            .with_integrity(Integrity::Blake3)
+            .with_signature(Signature::Ed25519);
+        let mut writer = Writer::with_header(&path, header)?;
+        let a = writer.push(&"alpha")?;
+        assert!(matches!(writer.checkpoint(a), Err(Error::MissingKey)));
+        let key = SigningKey::from_bytes(&[7; 32]);
+        writer.set_signing_key(key.clone());
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        reader.verify()?;
+        reader.verify_signed(&key.verifying_key())?;
+        let msg: String = reader.lookup(a.cast())?;
+        assert_eq!(msg, "alpha");
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(matches!(
+            reader.verify_signed(&other),
+            Err(Error::InvalidSignature(_))
+        ));
+        // unsigned files are rejected
+        let path = test_path("loam-unsigned.loam");
+        let header = Header::default().with_integrity(Integrity::Blake3);
+        let mut writer = Writer::with_header(&path, header)?;
+        let a = writer.push(&"alpha")?;
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        assert!(matches!(
+            reader.verify_signed(&key.verifying_key()),
+            Err(Error::InvalidSignature(_))
+        ));
+        // files without integrity hashes are rejected
+        let path = test_path("loam-unhashed.loam");
+        let header = Header::default().with_integrity(Integrity::None);
+        let mut writer = Writer::with_header(&path, header)?;
+        let a = writer.push(&"alpha")?;
+        writer.checkpoint(a)?;
+        let reader = Reader::new(&path)?;
+        assert!(matches!(
+            reader.verify_signed(&key.verifying_key()),
+            Err(Error::InvalidSignature(_))
+        ));
+        // checkpoint flags are signed
+        let path = test_path("loam-sign-flags.loam");
+        let header = Header::default()
+            .with_checksum(Checksum::None)
+            .with_integrity(Integrity::Blake3)
+            .with_signature(Signature::Ed25519);
+        let mut writer = Writer::with_header(&path, header)?;
+        writer.set_signing_key(key.clone());
+        let a = writer.push(&"alpha")?;
+        writer.checkpoint_named([("a", a.erase())])?;
+        drop(writer);
+        let reader = Reader::new(&path)?;
+        reader.verify_signed(&key.verifying_key())?;
+        let base = reader.checkpoint()?.id().to_usize();
+        drop(reader);
+        let mut buf = std::fs::read(&path)?;
+        assert_eq!(buf[base], CHECKPOINT | COMMIT);
+        buf[base] = CHECKPOINT;
+        std::fs::write(&path, buf)?;
+        let reader = Reader::new(&path)?;
+        assert!(matches!(
+            reader.verify_signed(&key.verifying_key()),
+            Err(Error::InvalidSignature(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(feature = "encrypt")]
+    #[test]
+    fn encrypt() -> Result<()> {
+        use crate::header::Encryption;
+        let path = test_path("loam-encrypt.loam");
+        let header =
+            Header::default().with_encryption(Encryption::XChaCha20Poly1305);
+        let mut writer = Writer::with_header(&path, header)?;
+        assert!(matches!(writer.push(&"secret"), Err(Error::MissingKey)));
+        writer.set_encryption_key([3; 32]);
+        let a = writer.push(&String::from("secret"))?;
+        let b = writer.push(&vec![a])?;
+        assert_eq!(writer.lookup(a)?, "secret");
+        writer.checkpoint_with(b, &"public")?;
+        let buf = std::fs::read(&path)?;
+        assert!(!buf.windows(6).any(|w| w == b"secret"));
+        let mut reader = Reader::new(&path)?;
+        assert_eq!(reader.header().encryption(), Encryption::XChaCha20Poly1305);
+        assert!(matches!(reader.lookup(a), Err(Error::MissingKey)));
+        reader.set_encryption_key([4; 32]);
+        assert!(matches!(reader.lookup(a), Err(Error::InvalidEncryption(_))));
+        reader.set_encryption_key([3; 32]);
+        let ids = reader.lookup(b)?;
+        assert_eq!(reader.lookup(ids[0])?, "secret");
+        assert!(matches!(
+            reader.lookup_borrowed::<&str>(a.cast()),
+            Err(Error::InvalidBorrow(_))
+        ));
+        let meta = reader.metadata(reader.checkpoint()?)?.unwrap();
+        assert_eq!(meta.payload::<String>()?, "public");
+        drop(reader);
+        drop(writer);
+        let mut writer = Writer::open(&path)?;
+        writer.set_encryption_key([3; 32]);
+        writer.set_dedup(true)?;
+        assert_eq!(writer.push(&String::from("secret"))?, a);
+        Ok(())
+    }
+
+    /// Storage which counts appends and syncs
+    #[derive(Default)]
+    struct Counting {
+        buf: Vec<u8>,
+        appends: usize,
+        syncs: Rc<Cell<usize>>,
+    }
+
+    impl Storage for Counting {
+        fn len(&self) -> Result<u64> {
+            Ok(self.buf.len() as u64)
+        }
+
+        fn append(&mut self, buf: &[u8]) -> Result<()> {
+            self.appends += 1;
+            self.buf.extend_from_slice(buf);
+            Ok(())
+        }
+
+        fn sync(&mut self) -> Result<()> {
+            self.syncs.set(self.syncs.get() + 1);
+            Ok(())
+        }
+
+        fn reader(&self) -> Result<Reader> {
+            Reader::from_bytes(self.buf.as_slice())
+        }
+    }
+
+    #[test]
+    fn durability() -> Result<()> {
+        let header = Header::default();
+        let mut writer = Writer::with_storage(Counting::default(), header)?;
+        let ids = (0..10u32)
+            .map(|i| writer.push(&i))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(writer.storage().appends, 1);
+        assert_eq!(writer.lookup(ids[9])?, 9);
+        assert_eq!(writer.storage().appends, 1);
+        writer.checkpoint(ids[0])?;
+        assert_eq!(writer.storage().syncs.get(), 1);
+        writer.set_durability(Durability::Every(3));
+        for id in &ids[1..7] {
+            writer.checkpoint(*id)?;
+        }
+        assert_eq!(writer.storage().syncs.get(), 3);
+        writer.set_durability(Durability::Interval(Duration::from_secs(3600)));
+        writer.checkpoint(ids[7])?;
+        writer.tick()?;
+        assert_eq!(writer.storage().syncs.get(), 3);
+        writer.set_durability(Durability::Interval(Duration::ZERO));
+        writer.tick()?;
+        assert_eq!(writer.storage().syncs.get(), 4);
+        writer.tick()?;
+        assert_eq!(writer.storage().syncs.get(), 4);
+        writer.set_durability(Durability::Never);
+        writer.checkpoint(ids[8])?;
+        assert_eq!(writer.storage().syncs.get(), 4);
+        writer.sync()?;
+        assert_eq!(writer.storage().syncs.get(), 5);
+        let reader = Reader::from_bytes(writer.into_storage().buf)?;
+        assert_eq!(reader.checkpoints()?.len(), 9);
+        assert_eq!(reader.lookup(reader.root()?.cast::<u32>())?, 8);
+        // unsynced checkpoints are synced on drop
+        let mut writer = Writer::with_storage(Counting::default(), header)?;
+        writer.set_durability(Durability::Never);
+        let syncs = Rc::clone(&writer.storage().syncs);
+        let id = writer.push(&0u32)?;
+        writer.checkpoint(id)?;
+        assert_eq!(syncs.get(), 0);
+        drop(writer);
+        assert_eq!(syncs.get(), 1);
+        // chunks after the last checkpoint are discarded on drop
+        for durability in [Durability::Checkpoint, Durability::Never] {
+            let path = test_path("loam-durability-drop.loam");
+            let mut writer = Writer::new(&path)?;
+            writer.set_durability(durability);
+            let id = writer.push(&"first")?;
+            writer.checkpoint(id)?;
+            writer.push(&"uncommitted")?;
+            drop(writer);
+            let mut writer = Writer::open(&path)?;
+            assert_eq!(writer.reader()?.root()?, id.erase());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn alignment() -> Result<()> {
+        let header = Header::default().with_alignment(Some(256));
+        let mut writer = Writer::with_storage(Vec::new(), header)?;
+        writer.set_compress_min(None);
+        let lens = [100, 100, 100, 300, 10, 240, 245, 250];
+        let mut ids = Vec::new();
+        for len in lens {
+            ids.push(writer.push(&vec![0xAAu8; len])?);
+        }
+        writer.checkpoint(ids[0])?;
+        let reader = Reader::from_bytes(writer.into_storage())?;
+        assert_eq!(reader.header().alignment(), Some(256));
+        let chunks = reader.chunks().collect::<Result<Vec<_>>>()?;
+        assert_eq!(chunks.len(), ids.len() + 1);
+        for ((chunk, id), len) in chunks.iter().zip(&ids).zip(lens) {
+            assert_eq!(chunk.id(), id.erase());
+            let start = chunk.id().to_usize();
+            let end = chunk.end() - 1;
+            assert!(start % 256 == 0 || start / 256 == end / 256);
+            assert_eq!(reader.lookup(*id)?, vec![0xAA; len]);
+        }
+        // large blocks with checksums
+        let block = 1 << 20;
+        let header = Header::default()
+            .with_checksum(Checksum::Crc32)
+            .with_alignment(Some(block));
+        let mut writer = Writer::with_storage(Vec::new(), header)?;
+        writer.set_compress_min(None);
+        let a = writer.push(&vec![0xAAu8; 100])?;
+        let b = writer.push(&vec![0xBBu8; block - 100])?;
+        writer.checkpoint(b)?;
+        assert_eq!(b.to_usize(), block);
+        let reader = Reader::from_bytes(writer.into_storage())?;
+        assert_eq!(reader.lookup(a)?.len(), 100);
+        assert_eq!(reader.lookup(b)?.len(), block - 100);
         Ok(())
     }
 }
@@ -3,11 +3,18 @@
 // Copyright (c) 2021-2026  Douglas P Lau
 //
 //! Data types for GIS
+use crate::node::type_tag;
+use loam::TypeTag;
 use pointy::{BBox, Bounded, Bounds, Float, Pt, Seg};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 /// GIS geometry which can be stored in an RTree
+///
+/// Stored geometry must also implement [TypeTag], so that opening a tree
+/// with the wrong types returns [Error::TypeMismatch].
+///
+/// [Error::TypeMismatch]: loam::Error::TypeMismatch
 pub trait Gis<F>
 where
     F: Float,
@@ -149,6 +156,13 @@ where
     }
 }
 
+impl<F, D> TypeTag for Points<F, D>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'P');
+}
+
 impl<F, D> Bounded<F> for &Points<F, D>
 where
     F: Float,
@@ -236,6 +250,13 @@ where
     }
 }
 
+impl<F, D> TypeTag for Linestrings<F, D>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'L');
+}
+
 impl<F, D> Bounded<F> for &Linestrings<F, D>
 where
     F: Float,
@@ -424,6 +445,13 @@ where
     }
 }
 
+impl<F, D> TypeTag for Polygons<F, D>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'G');
+}
+
 impl<F, D> Bounded<F> for &Polygons<F, D>
 where
     F: Float,
@@ -498,6 +526,13 @@ where
     }
 }
 
+impl<F, D> TypeTag for Geom<F, D>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'M');
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -505,8 +540,8 @@ mod test {
     #[test]
     fn clockwise() {
         let ring = Polygon::new([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
-        assert_eq!(false, ring.is_clockwise());
+        assert!(!ring.is_clockwise());
         let ring = Polygon::new([(0.0, 0.0), (0.0, 1.0), (1.0, 0.0)]);
-        assert_eq!(true, ring.is_clockwise());
+        assert!(ring.is_clockwise());
     }
 }
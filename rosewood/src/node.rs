@@ -2,7 +2,7 @@
 //
 // Copyright (c) 2021-2023  Douglas P Lau
 //
-use loam::Id;
+use loam::{Id, TypeTag};
 use pointy::{BBox, Bounded, Float, Pt};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -10,6 +10,13 @@ use std::cmp::Ordering;
 /// Number of elements per node
 pub const M_NODE: usize = 6;
 
+/// Make a type tag from a kind byte and float type
+///
+/// The float size is included, so that `f32` and `f64` trees differ.
+pub(crate) const fn type_tag<F>(kind: u8) -> u32 {
+    u32::from_le_bytes([b'R', b'W', kind, std::mem::size_of::<F>() as u8])
+}
+
 /// Entry in a file (geometry or node)
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct Entry<F>
@@ -189,3 +196,17 @@ where
         self.node
     }
 }
+
+impl<F> TypeTag for Node<F>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'N');
+}
+
+impl<F> TypeTag for Root<F>
+where
+    F: Float,
+{
+    const TAG: u32 = type_tag::<F>(b'R');
+}
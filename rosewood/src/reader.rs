@@ -4,7 +4,7 @@
 //
 use crate::gis::Gis;
 use crate::node::{M_NODE, Node, Root};
-use loam::{Error, Id, Reader, Result};
+use loam::{Error, Id, Reader, Result, TypeTag};
 use pointy::{BBox, Bounded, Float};
 use serde::de::DeserializeOwned;
 use std::marker::PhantomData;
@@ -18,7 +18,7 @@ use std::path::{Path, PathBuf};
 pub struct RTree<F, G>
 where
     F: Float + DeserializeOwned,
-    G: Gis<F> + DeserializeOwned,
+    G: Gis<F> + DeserializeOwned + TypeTag,
 {
     /// Path for file
     path: PathBuf,
@@ -31,7 +31,7 @@ where
 struct RTreeQuery<D, F, G>
 where
     F: Float + DeserializeOwned,
-    G: Gis<F, Data = D> + DeserializeOwned,
+    G: Gis<F, Data = D> + DeserializeOwned + TypeTag,
 {
     /// RTree reader
     reader: Option<Reader>,
@@ -52,7 +52,7 @@ where
 impl<D, F, G> Iterator for RTreeQuery<D, F, G>
 where
     F: Float + DeserializeOwned,
-    G: Gis<F, Data = D> + DeserializeOwned,
+    G: Gis<F, Data = D> + DeserializeOwned + TypeTag,
 {
     type Item = Result<G>;
 
@@ -63,7 +63,7 @@ where
         let reader = self.reader.as_ref()?;
         while let Some((id, height)) = self.work.pop() {
             if height > 1 {
-                match reader.lookup::<Node<F>>(id) {
+                match reader.lookup_tagged::<Node<F>>(id.cast()) {
                     Ok(node) => {
                         let children = node.into_entries();
                         for child in children {
@@ -76,7 +76,7 @@ where
                     Err(e) => return Some(Err(e)),
                 }
             } else {
-                match reader.lookup::<G>(id) {
+                match reader.lookup_tagged::<G>(id.cast()) {
                     Ok(geom) => return Some(Ok(geom)),
                     Err(e) => return Some(Err(e)),
                 }
@@ -89,7 +89,7 @@ where
 impl<D, F, G> RTreeQuery<D, F, G>
 where
     F: Float + DeserializeOwned,
-    G: Gis<F, Data = D> + DeserializeOwned,
+    G: Gis<F, Data = D> + DeserializeOwned + TypeTag,
 {
     /// Create a new RTree query
     fn new(tree: &RTree<F, G>, bbox: BBox<F>) -> Self {
@@ -111,7 +111,7 @@ where
         let mut work = Vec::new();
         let reader = Reader::new(path)?;
         let id = reader.root()?;
-        let root = reader.lookup::<Root<F>>(id)?;
+        let root = reader.lookup_tagged::<Root<F>>(id.cast())?;
         let height = Node::<F>::height(root.n_elem());
         log::trace!("root: {height}");
         let node = root.into_node();
@@ -138,7 +138,7 @@ where
 impl<D, F, G> RTree<F, G>
 where
     F: Float + DeserializeOwned,
-    G: Gis<F, Data = D> + DeserializeOwned,
+    G: Gis<F, Data = D> + DeserializeOwned + TypeTag,
 {
     /// Open an RTree `.loam` file for reading
     pub fn new<P>(path: P) -> Self
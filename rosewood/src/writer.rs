@@ -4,9 +4,10 @@
 //
 use crate::gis::Gis;
 use crate::node::{Entry, M_NODE, Node, Root};
-use loam::{Id, Reader, Result, Writer};
+use loam::{Header, Id, Result, TypeTag, Writer};
 use pointy::Float;
 use serde::{Serialize, de::DeserializeOwned};
+use std::fs::OpenOptions;
 use std::io::ErrorKind;
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
@@ -73,20 +74,24 @@ where
 ///    when reading.
 /// 2. All `Node` values, in depth-first order, with the root appearing last.
 ///
+/// Pushed `Gis` values are first written to a scratch file (`.tmp`), and
+/// read back while building the tree.  The tree is written to a temporary
+/// file (`.tmp2`), which replaces the destination file when finished.
+///
 /// [OMT]: http://ceur-ws.org/Vol-74/files/FORUM_18.pdf
 pub struct BulkWriter<D, F, G>
 where
     F: Float + Serialize + DeserializeOwned,
-    G: Gis<F, Data = D> + Serialize + DeserializeOwned,
+    G: Gis<F, Data = D> + Serialize + DeserializeOwned + TypeTag,
 {
     /// Path to file
     path: PathBuf,
 
-    /// Writer to temporary file
-    writer: Writer,
+    /// Writer to scratch file
+    scratch: Writer,
 
-    /// Reader for temporary file
-    reader: Reader,
+    /// Writer to temporary tree file
+    writer: Writer,
 
     /// Gis entries
     elems: Vec<Entry<F>>,
@@ -106,36 +111,40 @@ where
 }
 
 /// Make a loam writer, overwriting file if it exists
+///
+/// The file is private, so no writer lock is needed.  Type tags are
+/// enabled, so opening a tree with the wrong types returns an error.
 fn make_writer(path: &Path) -> Result<Writer> {
-    match Writer::new(path) {
-        Err(loam::Error::Io(e)) if e.kind() == ErrorKind::AlreadyExists => {
-            std::fs::remove_file(path)?;
-            Writer::new(path)
-        }
-        w => w,
+    if let Err(e) = std::fs::remove_file(path)
+        && e.kind() != ErrorKind::NotFound
+    {
+        return Err(e.into());
     }
+    let file = OpenOptions::new()
+        .create_new(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    Writer::with_storage(file, Header::default().with_type_tags(true))
 }
 
 impl<D, F, G> BulkWriter<D, F, G>
 where
     F: Float + Serialize + DeserializeOwned,
-    G: Gis<F, Data = D> + Serialize + DeserializeOwned,
+    G: Gis<F, Data = D> + Serialize + DeserializeOwned + TypeTag,
 {
     /// Create a new bulk writer
     pub fn new<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let mut tmp = PathBuf::new();
-        tmp.push(path);
-        let path = tmp.clone();
-        tmp.set_extension("tmp");
-        let writer = make_writer(&tmp)?;
-        let reader = Reader::new_empty()?;
+        let path = path.as_ref().to_path_buf();
+        let scratch = make_writer(&tmp_path(&path, "tmp"))?;
+        let writer = make_writer(&tmp_path(&path, "tmp2"))?;
         Ok(Self {
             path,
+            scratch,
             writer,
-            reader,
             elems: Vec::new(),
             nodes: Vec::new(),
             odd_axis: Axis::X,
@@ -147,9 +156,9 @@ where
 
     /// Push geometry
     pub fn push(&mut self, geom: &G) -> Result<()> {
-        let id = self.writer.push(geom)?;
+        let id = self.scratch.push_tagged(geom)?;
         let bbox = geom.bbox();
-        self.elems.push(Entry::new(id, bbox));
+        self.elems.push(Entry::new(id.erase(), bbox));
         Ok(())
     }
 
@@ -161,31 +170,25 @@ where
             self.cancel()?;
             return Err(loam::Error::InvalidCheckpoint);
         }
-        // finish writing to the temp file
-        self.writer.checkpoint(Id::new(0))?;
-        // open another file for the real tree
-        let mut tmp = PathBuf::new();
-        tmp.push(&self.path);
-        tmp.set_extension("tmp2");
-        self.writer = Writer::new(&tmp)?;
-        // reopen the temp file for reading
-        tmp.set_extension("tmp");
-        self.reader = Reader::new(&tmp)?;
         self.build_tree(&mut elems)?;
         let id = self.write_nodes(elems.len())?;
         self.writer.checkpoint(id)?;
         let path = self.path;
+        drop(self.scratch);
         drop(self.writer);
-        remove_tmp_file(&path)?;
-        rename_tree(&path)?;
+        std::fs::remove_file(tmp_path(&path, "tmp"))?;
+        std::fs::rename(tmp_path(&path, "tmp2"), &path)?;
         Ok(())
     }
 
     /// Cancel building RTree
     pub fn cancel(self) -> Result<()> {
         let path = self.path;
+        drop(self.scratch);
         drop(self.writer);
-        remove_tmp_file(&path)
+        std::fs::remove_file(tmp_path(&path, "tmp"))?;
+        std::fs::remove_file(tmp_path(&path, "tmp2"))?;
+        Ok(())
     }
 
     /// Build the tree recursively
@@ -260,48 +263,37 @@ where
     fn build_leaf(&mut self, elems: &[Entry<F>]) -> Result<usize> {
         let mut leaf = Node::<F>::new();
         for entry in elems {
-            let geom: G = self.reader.lookup(entry.id())?;
-            let wid = self.writer.push(&geom)?;
-            leaf.push(wid, entry.bbox());
+            let geom: G = self.scratch.lookup_tagged(entry.id().cast())?;
+            let wid = self.writer.push_tagged(&geom)?;
+            leaf.push(wid.erase(), entry.bbox());
         }
         Ok(self.push_node(NodeElem::Leaf(leaf)))
     }
 
     /// Write out all nodes
-    fn write_nodes(&mut self, n_elems: usize) -> Result<Id> {
+    fn write_nodes(&mut self, n_elems: usize) -> Result<Id<Root<F>>> {
         assert!(n_elems > 0);
         let n_nodes = self.nodes.len();
         let mut node_entries = Vec::with_capacity(n_nodes);
         for ne in &self.nodes[..n_nodes - 1] {
             let node = ne.lookup(&node_entries);
-            let id = self.writer.push(&node)?;
+            let id = self.writer.push_tagged(&node)?;
             let bbox = node.bbox();
-            node_entries.push(Entry::new(id, bbox));
+            node_entries.push(Entry::new(id.erase(), bbox));
         }
         let ne = &self.nodes[n_nodes - 1];
         let node = ne.lookup(&node_entries);
         let root = Root::new(node, n_elems);
-        let id = self.writer.push(&root)?;
+        let id = self.writer.push_tagged(&root)?;
         Ok(id)
     }
 }
 
-/// Remove the temporary file
-fn remove_tmp_file(path: &Path) -> Result<()> {
-    let mut tmp = PathBuf::new();
-    tmp.push(path);
-    tmp.set_extension("tmp");
-    std::fs::remove_file(tmp)?;
-    Ok(())
-}
-
-/// Rename tree file
-fn rename_tree(path: &Path) -> Result<()> {
-    let mut tmp2 = PathBuf::new();
-    tmp2.push(path);
-    tmp2.set_extension("tmp2");
-    std::fs::rename(tmp2, path)?;
-    Ok(())
+/// Get path of a temporary file
+fn tmp_path(path: &Path, extension: &str) -> PathBuf {
+    let mut tmp = path.to_path_buf();
+    tmp.set_extension(extension);
+    tmp
 }
 
 #[cfg(test)]
@@ -320,4 +312,27 @@ mod test {
         assert_eq!(Axis::X, axis.with_height(2));
         assert_eq!(Axis::Y, axis.with_height(1));
     }
+
+    #[test]
+    fn type_mismatch() -> Result<()> {
+        use crate::RTree;
+        use crate::gis::Points;
+        use pointy::BBox;
+        let path = std::env::temp_dir().join("rosewood-type-mismatch.loam");
+        let mut writer = BulkWriter::new(&path)?;
+        for i in 0..20 {
+            let mut pts = Points::new(());
+            pts.push((i as f32, i as f32));
+            writer.push(&pts)?;
+        }
+        writer.finish()?;
+        let rtree = RTree::<f32, Points<f32, ()>>::new(&path);
+        let bbox = BBox::new([(0.0, 0.0), (100.0, 100.0)]);
+        assert_eq!(rtree.query(bbox).collect::<Result<Vec<_>>>()?.len(), 20);
+        let rtree = RTree::<f64, Points<f64, ()>>::new(&path);
+        let bbox = BBox::new([(0.0, 0.0), (100.0, 100.0)]);
+        let res = rtree.query(bbox).collect::<Result<Vec<_>>>();
+        assert!(matches!(res, Err(loam::Error::TypeMismatch(_))));
+        Ok(())
+    }
 }